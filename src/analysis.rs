@@ -0,0 +1,134 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    board::{Color, Game, GameState, Score},
+    eval::{self, ChainScore, Evaluation, Evaluator, Weighted},
+    zugzwang::{self, Outcome},
+};
+
+const BAR_WIDTH: usize = 30;
+
+#[derive(Clone, Debug)]
+pub struct ColumnAnalysis {
+    pub column: usize,
+    pub score: Score,
+    // Principal variation in play order, starting with the column
+    pub line: Vec<usize>,
+    // Moves of the winner until the game ends, positive if the side to move wins
+    pub mate: Option<isize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub turn: Color,
+    pub k: usize,
+    // Best column for the side to move first
+    pub columns: Vec<ColumnAnalysis>,
+    // Outcome of the position by the odd/even threat rules
+    pub prediction: Option<Outcome>,
+}
+
+// Searches every legal column separately instead of only the best one, `depth` counts the
+// column itself
+pub fn analyse(game: &mut Game, evaluation: &Evaluation, depth: usize) -> Analysis {
+    let turn = game.turn();
+    let mut columns = Vec::new();
+    if matches!(game.game_state(), GameState::InProgress) {
+        for column in game.legal_moves() {
+            let (score, mut line) = match game.run_unchecked(column).unwrap() {
+                GameState::Win(winner) => (ChainScore.win(game, winner, 0), Vec::new()),
+                GameState::Draw => (ChainScore.draw(game), Vec::new()),
+                GameState::InProgress => {
+                    let (score, pv) = eval::search(game, evaluation, depth.saturating_sub(1));
+                    (score, pv.into_iter().rev().collect())
+                },
+            };
+            game.undo_unchecked();
+
+            line.insert(0, column);
+            let mate = eval::mate_in(&score, game);
+            columns.push(ColumnAnalysis { column, score, line, mate });
+        }
+    }
+
+    columns.sort_by(|a, b| match turn {
+        Color::Red => b.score.cmp(&a.score),
+        Color::Yellow => a.score.cmp(&b.score),
+    });
+    Analysis { turn, k: game.k(), columns, prediction: zugzwang::analyse(game).prediction }
+}
+
+impl ColumnAnalysis {
+    // The score as a single number for the side to move, or the mate distance
+    fn value(&self, turn: Color, k: usize) -> Result<i64, isize> {
+        if let Some(moves) = self.mate {
+            return Err(moves);
+        }
+        let value = Weighted::for_k(k).value(&self.score);
+        Ok(match turn {
+            Color::Red => value,
+            Color::Yellow => -value,
+        })
+    }
+}
+
+// One bar per column, longer for better columns, with wins full and losses empty
+impl Display for Analysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let values: Vec<Result<i64, isize>> = self.columns.iter().map(|column| column.value(self.turn, self.k)).collect();
+        let finite = values.iter().filter_map(|value| value.ok());
+        let (low, high) = (finite.clone().min().unwrap_or(0), finite.max().unwrap_or(0));
+
+        for (column, value) in self.columns.iter().zip(&values) {
+            let (length, label) = match *value {
+                Err(moves) => (if moves > 0 { BAR_WIDTH } else { 0 }, eval::describe_mate(moves)),
+                // The worst column still gets a sliver, so it can be told apart from a loss
+                Ok(value) if high == low => (BAR_WIDTH / 2, format!("{value:+}")),
+                Ok(value) => (1 + ((value - low) as f64 / (high - low) as f64 * (BAR_WIDTH - 1) as f64).round() as usize, format!("{value:+}")),
+            };
+            let line: Vec<String> = column.line.iter().map(|column| (column + 1).to_string()).collect();
+            writeln!(f, "{:>3} {:<width$} {:>11}  {}", column.column + 1, "█".repeat(length), label, line.join(" "), width = BAR_WIDTH)?;
+        }
+        writeln!(f, "Threat parity: {}", zugzwang::describe(self.prediction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(moves: &[usize]) -> Game {
+        let mut game = Game::new(7, 6, 4);
+        for &column in moves {
+            game.run(column).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn winning_column_comes_first() {
+        // Red completes the first column, then Yellow the second one
+        for (moves, win) in [(&[0, 1, 0, 1, 0, 1][..], 0), (&[0, 1, 0, 1, 6, 1, 6][..], 1)] {
+            let mut game = game(moves);
+            let analysis = analyse(&mut game, &Evaluation::Default, 3);
+
+            let best = &analysis.columns[0];
+            assert_eq!((best.column, best.mate, &best.line[..]), (win, Some(1), &[win][..]));
+            assert_eq!(analysis.columns.len(), 7);
+            assert_eq!(game.move_list(), moves);
+            assert!(analysis.to_string().lines().next().unwrap().contains("win in 1"));
+        }
+    }
+
+    #[test]
+    fn every_column_but_the_block_loses() {
+        // Yellow has to cover Red's column
+        let mut game = game(&[0, 1, 0, 1, 0]);
+        let analysis = analyse(&mut game, &Evaluation::Default, 3);
+
+        assert_eq!(analysis.turn, Color::Yellow);
+        assert_eq!(analysis.columns[0].column, 0);
+        assert_eq!(analysis.columns[0].mate, None);
+        assert!(analysis.columns[1..].iter().all(|column| column.mate == Some(-1)));
+    }
+}
@@ -0,0 +1,75 @@
+use std::{env, fmt::{self, Display, Formatter}, io::{stdout, IsTerminal}};
+
+use crate::board::{Color, Game};
+
+pub const RESET: &str = "\x1b[0m";
+pub const RED: &str = "\x1b[31m";
+pub const YELLOW: &str = "\x1b[33m";
+pub const DIM: &str = "\x1b[2m";
+pub const BOLD: &str = "\x1b[1m";
+pub const HIGHLIGHT: &str = "\x1b[42m";
+
+pub struct Ansi<'a> {
+    game: &'a Game,
+    color: bool,
+}
+
+impl<'a> Ansi<'a> {
+    pub fn new(game: &'a Game, color: bool) -> Self {
+        Self { game, color }
+    }
+
+    // Colors only if stdout is a terminal and NO_COLOR is not set
+    pub fn auto(game: &'a Game) -> Self {
+        Self::new(game, color_enabled())
+    }
+}
+
+pub fn color_enabled() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && stdout().is_terminal()
+}
+
+impl Display for Ansi<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let game = self.game;
+        // Wide enough for the largest column number plus a space
+        let cell = game.width().to_string().len() + 1;
+        let last_move = game.last_move();
+        let winning_line = game.winning_line().unwrap_or_default();
+
+        for row in (0..game.height()).rev() {
+            for column in 0..game.width() {
+                let field = game.get(column, row);
+                if !self.color {
+                    let symbol = match field {
+                        Some(Color::Red) => 'X',
+                        Some(Color::Yellow) => 'O',
+                        None => '_',
+                    };
+                    write!(f, "{symbol:<cell$}")?;
+                    continue;
+                }
+
+                if winning_line.contains(&(column, row)) {
+                    write!(f, "{HIGHLIGHT}")?;
+                } else if last_move == Some((column, row)) {
+                    write!(f, "{BOLD}")?;
+                }
+                match field {
+                    Some(Color::Red) => write!(f, "{RED}●")?,
+                    Some(Color::Yellow) => write!(f, "{YELLOW}●")?,
+                    None => write!(f, "{DIM}·")?,
+                }
+                write!(f, "{RESET}{:<1$}", "", cell - 1)?;
+            }
+            writeln!(f)?;
+        }
+
+        // Column numbers match the 1-based input of the prompt
+        for column in 1..=game.width() {
+            write!(f, "{column:<cell$}")?;
+        }
+        writeln!(f)
+    }
+}
@@ -0,0 +1,142 @@
+use std::{ops::ControlFlow, time::{Duration, Instant}};
+
+use crate::{board::{Game, SearchLimits}, cli::parse_moves, eval::{self, Evaluation}};
+
+pub struct BenchOptions {
+    pub perft_depth: usize,
+    pub search_depth: usize,
+    pub divide: bool,
+    // Print the statistics of every iteration of the search
+    pub verbose: bool,
+}
+
+struct Position {
+    name: &'static str,
+    width: usize,
+    height: usize,
+    k: usize,
+    moves: &'static str,
+    // Known perft results for depth 1, 2, ...
+    perft: &'static [u64],
+}
+
+const SUITE: [Position; 5] = [
+    Position {
+        name: "empty 7x6",
+        width: 7, height: 6, k: 4,
+        moves: "",
+        perft: &[7, 49, 343, 2401, 16807, 117649, 823536, 5673234],
+    },
+    Position {
+        name: "opening 7x6",
+        width: 7, height: 6, k: 4,
+        moves: "4,4,3,5,4",
+        perft: &[7, 49, 343, 2400, 16356, 113446, 761273, 5163223],
+    },
+    Position {
+        name: "middlegame 7x6",
+        width: 7, height: 6, k: 4,
+        moves: "4,4,4,3,5,5,3,6,6,2,2,4",
+        perft: &[7, 49, 342, 2376, 16293, 108465, 722944, 4595344],
+    },
+    Position {
+        name: "empty 9x7 k5",
+        width: 9, height: 7, k: 5,
+        moves: "",
+        perft: &[9, 81, 729, 6561, 59049, 531441, 4782969, 43046712],
+    },
+    Position {
+        name: "empty 5x4 k3",
+        width: 5, height: 4, k: 3,
+        moves: "",
+        perft: &[5, 25, 125, 625, 3120, 14020, 65330, 269032],
+    },
+];
+
+fn rate(nodes: u64, elapsed: Duration) -> u64 {
+    (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64
+}
+
+fn game(position: &Position) -> Game {
+    let mut game = Game::new(position.width, position.height, position.k);
+    for column in parse_moves(position.moves).unwrap() {
+        let _ = game.run(column);
+    }
+    game
+}
+
+// Returns false if a perft result differs from the known one
+pub fn run(options: &BenchOptions) -> bool {
+    let mut correct = true;
+    let (mut perft_nodes, mut perft_time) = (0, Duration::ZERO);
+    let (mut search_nodes, mut search_time) = (0, Duration::ZERO);
+
+    println!("{:<16} {:>6} {:>12} {:>10} {:>12}  Check", "Perft", "Depth", "Nodes", "Time (ms)", "Nodes/s");
+    for position in &SUITE {
+        let mut game = game(position);
+        let start = Instant::now();
+        let nodes = game.perft(options.perft_depth);
+        let elapsed = start.elapsed();
+        perft_nodes += nodes;
+        perft_time += elapsed;
+
+        let check = match position.perft.get(options.perft_depth.wrapping_sub(1)) {
+            Some(&expected) if expected == nodes => "ok".to_string(),
+            Some(&expected) => {
+                correct = false;
+                format!("MISMATCH, expected {expected}")
+            },
+            None => "-".to_string(),
+        };
+        println!("{:<16} {:>6} {:>12} {:>10} {:>12}  {check}",
+            position.name, options.perft_depth, nodes, elapsed.as_millis(), rate(nodes, elapsed));
+
+        if options.divide {
+            for (column, nodes) in game.perft_divide(options.perft_depth) {
+                println!("  {:>2}: {nodes}", column + 1);
+            }
+        }
+    }
+
+    // The search of the computer player, with the tuned weights and the tablebase if they are loaded
+    let evaluation = Evaluation::Default;
+    println!();
+    println!("Search of the computer player with {evaluation}");
+    println!("{:<16} {:>6} {:>12} {:>10} {:>10} {:>10} {:>12}  Best move", "Search", "Depth", "Nodes", "Cutoffs", "Hints", "Time (ms)", "Nodes/s");
+    for position in &SUITE {
+        let mut game = game(position);
+        let (_score, pv, stats) = eval::search_with_info(&mut game, &evaluation, options.search_depth, &SearchLimits::default(), |stats, _, _| {
+            if options.verbose {
+                println!("  {stats}");
+            }
+            ControlFlow::Continue(())
+        });
+        search_nodes += stats.nodes;
+        search_time += stats.elapsed;
+
+        println!("{:<16} {:>6} {:>12} {:>10} {:>10} {:>10} {:>12}  {}",
+            position.name, stats.depth, stats.nodes, stats.cutoffs, stats.move_hints, stats.elapsed.as_millis(), stats.nps(),
+            pv.last().map_or(0, |column| column + 1));
+    }
+
+    println!();
+    println!("Perft total: {perft_nodes} nodes in {} ms, {} nodes/s", perft_time.as_millis(), rate(perft_nodes, perft_time));
+    println!("Search total: {search_nodes} nodes in {} ms, {} nodes/s", search_time.as_millis(), rate(search_nodes, search_time));
+
+    correct
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suite_perft_counts_are_known() {
+        for position in &SUITE {
+            let mut game = game(position);
+            for (depth, &expected) in position.perft.iter().enumerate().take(4) {
+                assert_eq!(game.perft(depth + 1), expected, "{} at depth {}", position.name, depth + 1);
+            }
+        }
+    }
+}
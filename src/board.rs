@@ -1,4 +1,12 @@
-use std::{fmt::{self, Display, Formatter}, ops::{AddAssign, SubAssign}};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    ops::{AddAssign, ControlFlow, SubAssign},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::{Duration, Instant},
+};
+
+use crate::{eval::Evaluator, history::{NodeId, VariationTree}};
 
 #[derive(Clone, Copy, Debug)]
 #[derive(PartialEq, Eq)]
@@ -18,7 +26,7 @@ impl Display for Color {
 }
 
 impl Color {
-    fn other(&self) -> Self {
+    pub fn other(&self) -> Self {
         use Color::{Red, Yellow};
         match self {
             Red => Yellow,
@@ -45,6 +53,12 @@ impl Ord for Score {
     }
 }
 
+impl Score {
+    pub fn values(&self) -> &[i32] {
+        &self.0
+    }
+}
+
 impl From<Vec<i32>> for Score {
     fn from(value: Vec<i32>) -> Self {
         Self(value.into())
@@ -74,14 +88,96 @@ pub enum GameState {
     InProgress,
 }
 
-pub enum InsertError {
-    InvalidColumn,
-    ColumnFull,
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchStats {
+    pub nodes: u64,
+    pub cutoffs: u64,
+    // Positions whose moves were ordered by the best move of an earlier iteration
+    pub move_hints: u64,
+    pub depth: usize,
+    pub elapsed: Duration,
+}
+
+impl SearchStats {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.elapsed.as_secs_f64().max(1e-9)) as u64
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "depth {} nodes {} cutoffs {} hints {} time {} nps {}",
+            self.depth, self.nodes, self.cutoffs, self.move_hints, self.elapsed.as_millis(), self.nps())
+    }
+}
+
+// Best move of every searched position by its hash, tried first when the position comes up
+// again. Only the move ordering is reused, so the value of the root stays that of the plain
+// search, but among moves of equal value the best move and principal variation may differ.
+#[derive(Clone, Debug, Default)]
+pub struct MoveTable {
+    moves: HashMap<u64, usize>,
+}
+
+impl MoveTable {
+    // Bounds the memory of a table that is kept between searches
+    const CAPACITY: usize = 1 << 22;
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.moves.clear();
+    }
+}
+
+// Ends a search early, which then returns the result of the last completed depth
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub stop: Option<Arc<AtomicBool>>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
 }
 
-pub enum ExtractError {
+struct Context<'a, E> {
+    evaluator: &'a E,
+    move_order: &'a [usize],
+    table: &'a mut MoveTable,
+    cutoffs: u64,
+    move_hints: u64,
+    // None while the limits do not apply, so that the first depth always completes
+    limits: Option<&'a SearchLimits>,
+    start: Instant,
+    first_node: u64,
+    polls: u64,
+    aborted: bool,
+}
+
+impl<'a, E> Context<'a, E> {
+    fn new(evaluator: &'a E, move_order: &'a [usize], table: &'a mut MoveTable, first_node: u64) -> Self {
+        Self { evaluator, move_order, table, cutoffs: 0, move_hints: 0, limits: None, start: Instant::now(), first_node, polls: 0, aborted: false }
+    }
+
+    // Polled at every node, but the clock is only read every so often
+    fn stopped(&mut self, nodes: u64) -> bool {
+        const CLOCK_INTERVAL: u64 = 1024;
+
+        if let (Some(limits), false) = (self.limits, self.aborted) {
+            self.polls += 1;
+            self.aborted = limits.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+                || limits.nodes.is_some_and(|limit| nodes - self.first_node >= limit)
+                || self.polls.is_multiple_of(CLOCK_INTERVAL) && limits.time.is_some_and(|time| self.start.elapsed() >= time);
+        }
+        self.aborted
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum InsertError {
     InvalidColumn,
-    ColumnEmpty,
+    ColumnFull,
+    GameOver,
 }
 
 #[derive(Debug)]
@@ -89,7 +185,59 @@ pub enum DeserializeError {
     EmptyInput,
     DifferentWidths,
     BadSymbol(char),
-    UnreachablePosition
+    UnreachablePosition,
+    TooSmall,
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use DeserializeError::*;
+        match self {
+            EmptyInput => write!(f, "the grid is empty"),
+            DifferentWidths => write!(f, "the rows have different widths"),
+            BadSymbol(symbol) => write!(f, "'{symbol}' is not a symbol of the grid"),
+            UnreachablePosition => write!(f, "the position cannot be reached by legal moves"),
+            TooSmall => write!(f, "the grid must be at least k wide and k high"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct Symbols {
+    pub red: char,
+    pub yellow: char,
+    pub empty: char,
+}
+
+impl Symbols {
+    pub const XO: Self = Self { red: 'X', yellow: 'O', empty: '_' };
+    pub const RY: Self = Self { red: 'R', yellow: 'Y', empty: '.' };
+    pub const LOWERCASE: Self = Self { red: 'x', yellow: 'o', empty: '-' };
+    pub const PRESETS: [Self; 3] = [Self::XO, Self::RY, Self::LOWERCASE];
+
+    // The first preset that accounts for every symbol in the input
+    pub fn detect(input: &str) -> Option<Self> {
+        let symbols: Vec<char> = input.chars().filter(|c| !c.is_whitespace()).collect();
+
+        Self::PRESETS.into_iter()
+            .find(|preset| symbols.iter().all(|&symbol| preset.field(symbol).is_some()))
+    }
+
+    fn field(&self, symbol: char) -> Option<Field> {
+        match symbol {
+            s if s == self.red => Some(Some(Color::Red)),
+            s if s == self.yellow => Some(Some(Color::Yellow)),
+            s if s == self.empty => Some(None),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Self::XO
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -102,7 +250,12 @@ pub struct Game {
     full_spaces: Box<[usize]>,
     move_list: Vec<usize>,
     score_list: Vec<Score>,
-    game_state: GameState
+    game_state: GameState,
+    history: VariationTree,
+    // Positions visited by search since the last reset
+    nodes: u64,
+    // Zobrist hash of the chips on the board
+    hash: u64,
 }
 
 impl Game {
@@ -111,13 +264,16 @@ impl Game {
         Self {
             width: m,
             height: n,
-            k: k,
+            k,
             board: vec![None; m * n].into(),
             full_spaces: vec![0; m].into(),
             score_list: vec![vec![0;k].into()],
             turn: Color::Red,
             move_list: Vec::new(),
             game_state: GameState::InProgress,
+            history: VariationTree::new(),
+            nodes: 0,
+            hash: size_key(m, n, k),
         }
     }
 
@@ -141,84 +297,185 @@ impl Game {
         self.turn
     }
 
-    pub fn minimax_iterative(&mut self, depth: usize) {
-        let mut dfs_stack: Box<[_]> = vec![0; depth].into();
+    #[inline(always)]
+    pub fn game_state(&self) -> GameState {
+        self.game_state
+    }
+
+    #[inline(always)]
+    pub fn move_list(&self) -> &[usize] {
+        &self.move_list
+    }
+
+    #[inline(always)]
+    pub fn history(&self) -> &VariationTree {
+        &self.history
+    }
+
+    // Row 0 is the bottom row
+    #[inline(always)]
+    pub fn get(&self, column: usize, row: usize) -> Option<Color> {
+        self.board[self.height * column + row]
+    }
+
+    pub fn legal_moves(&self) -> Vec<usize> {
+        (0..self.width).filter(|&column| self.full_spaces[column] < self.height).collect()
+    }
+
+    pub fn last_move(&self) -> Option<(usize, usize)> {
+        let &column = self.move_list.last()?;
+        Some((column, self.full_spaces[column] - 1))
+    }
 
-        let mut i = 0;
-        for _ in 0..depth {
-            while self.run_unchecked(i).is_none() && i < self.width {
-                self.run_unchecked(i);
-                i += 1;
+    pub fn winning_line(&self) -> Option<Vec<(usize, usize)>> {
+        let directions = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+        for column in 0..self.width {
+            for row in 0..self.height {
+                let Some(color) = self.get(column, row) else { continue };
+                for (dx, dy) in directions {
+                    let line: Vec<(usize, usize)> = (0..self.k as isize)
+                        .map(|i| (column as isize + dx * i, row as isize + dy * i))
+                        .take_while(|&(x, y)| {
+                            (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&y)
+                        })
+                        .map(|(x, y)| (x as usize, y as usize))
+                        .take_while(|&(x, y)| self.get(x, y) == Some(color))
+                        .collect();
+                    if line.len() == self.k { return Some(line) }
+                }
             }
         }
 
-        loop {
-            dfs_stack[depth - 1] += 1;
-            self.undo();
+        None
+    }
 
-            let mut depth_index = depth - 1;
-            while dfs_stack[depth_index] == self.width {
-                let Some(_) = self.undo() else { return };
+    // Empty cells that would complete k in a row for `color`, regardless of whether they are playable yet
+    pub fn threats(&self, color: Color) -> Vec<(usize, usize)> {
+        let directions = [(1, 0), (0, 1), (1, 1), (1, -1)];
+        let count = |column: usize, row: usize, dx: isize, dy: isize| {
+            (1..self.k as isize)
+                .map(|i| (column as isize + dx * i, row as isize + dy * i))
+                .take_while(|&(x, y)| {
+                    (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&y)
+                })
+                .take_while(|&(x, y)| self.get(x as usize, y as usize) == Some(color))
+                .count()
+        };
 
-                dfs_stack[depth_index] = 0;
-                depth_index -= 1;
-                dfs_stack[depth_index] += 1;
-            }
-            for i in depth_index..depth {
-                self.run_unchecked(dfs_stack[i]);
+        let mut threats = Vec::new();
+        for column in 0..self.width {
+            for row in self.full_spaces[column]..self.height {
+                let is_threat = directions.iter().any(|&(dx, dy)| {
+                    count(column, row, dx, dy) + count(column, row, -dx, -dy) + 1 >= self.k
+                });
+                if is_threat { threats.push((column, row)) }
             }
+        }
 
-            if self.board.iter().filter(|x| x.is_some()).count() == 7 {
-                println!("{dfs_stack:?}");
-                println!("{self}");
-                println!("{:?}", self.last_score());
-                println!();
-            }
+        threats
+    }
+
+    pub fn minimax_with<E: Evaluator>(&mut self, evaluator: &E, depth: usize) -> (E::Value, Vec<usize>) {
+        let move_order = self.move_order();
+        let mut table = MoveTable::default();
+        let mut context = Context::new(evaluator, &move_order, &mut table, self.nodes);
+        self.minimax_root(&mut context, depth)
+    }
+
+    // Iterative deepening up to `depth` or the limits, calling `info` after every completed
+    // depth, which can also end the search there. The table carries the best moves from one
+    // depth, and from one search, to the next.
+    pub fn search_with<E: Evaluator>(
+        &mut self,
+        evaluator: &E,
+        depth: usize,
+        table: &mut MoveTable,
+        limits: &SearchLimits,
+        mut info: impl FnMut(&mut Game, &SearchStats, &E::Value, &[usize]) -> ControlFlow<()>,
+    ) -> (E::Value, Vec<usize>, SearchStats) {
+        if table.len() > MoveTable::CAPACITY {
+            table.clear();
+        }
+        let move_order = self.move_order();
+        let mut context = Context::new(evaluator, &move_order, table, self.nodes);
+        // Deeper searches than the empty cells give the same result
+        let depth = depth.min(self.width * self.height - self.move_list.len()).max(1);
+
+        let mut stats = SearchStats::default();
+        let mut result = (evaluator.draw(self), Vec::new());
+        for iteration in 1..=depth {
+            let iteration_result = self.minimax_root(&mut context, iteration);
+            stats = SearchStats {
+                nodes: self.nodes - context.first_node,
+                cutoffs: context.cutoffs,
+                move_hints: context.move_hints,
+                depth: if context.aborted { stats.depth } else { iteration },
+                elapsed: context.start.elapsed(),
+            };
+            if context.aborted { break }
+
+            result = iteration_result;
+            if info(self, &stats, &result.0, &result.1).is_break() { break }
+            context.limits = Some(limits);
         }
 
-        todo!()
+        (result.0, result.1, stats)
     }
 
-    pub fn minimax_rec(&mut self, depth: usize) -> (Score, Vec<usize>) {
-        let move_order = {
-            let width = self.width as i32;
-            let mut acc = width / 2;
-            let mut sign = -1;
-            let mut res = Vec::new();
+    fn move_order(&self) -> Vec<usize> {
+        let width = self.width as i32;
+        let mut acc = width / 2;
+        let mut sign = -1;
+        let mut res = Vec::new();
 
-            for i in 1..=width {
-                res.push(acc as usize);
-                acc += sign * i;
-                sign = -sign;
-            }
+        for i in 1..=width {
+            res.push(acc as usize);
+            acc += sign * i;
+            sign = -sign;
+        }
 
-            res
-        };
-        let alpha = vec![i32::MIN; 4].into();
-        let beta = vec![i32::MAX; 4].into();
+        res
+    }
 
+    fn minimax_root<E: Evaluator>(&mut self, context: &mut Context<E>, depth: usize) -> (E::Value, Vec<usize>) {
+        let (alpha, beta) = context.evaluator.bounds(self);
 
         if self.turn() == Color::Red {
-            self.minimax_rec_inner_red(depth, alpha, beta, &move_order)
+            self.minimax_rec_inner_red(context, depth, alpha, beta)
         } else {
-            self.minimax_rec_inner_yellow(depth, alpha, beta, &move_order)
+            self.minimax_rec_inner_yellow(context, depth, alpha, beta)
         }
     }
 
-    fn minimax_rec_inner_red(&mut self, depth: usize, mut alpha: Score, beta: Score, move_order: &[usize]) -> (Score, Vec<usize>) {
-        if depth == 0 {
-            return (self.last_score().clone(), Vec::new());
+    // A move of another position with the same hash may be outside the board
+    fn table_move<E>(&self, context: &mut Context<E>) -> Option<usize> {
+        let column = context.table.moves.get(&self.hash).copied().filter(|&column| column < self.width);
+        if column.is_some() { context.move_hints += 1 }
+        column
+    }
+
+    fn minimax_rec_inner_red<E: Evaluator>(&mut self, context: &mut Context<E>, depth: usize, mut alpha: E::Value, beta: E::Value) -> (E::Value, Vec<usize>) {
+        // The value does not matter once the search is aborted, the depth is thrown away
+        if depth == 0 || context.stopped(self.nodes) {
+            return (context.evaluator.evaluate(self), Vec::new());
         }
 
         let mut best_moves = Vec::new();
         let mut best_move = 0;
-        let mut best_score = vec![i32::MIN; self.k].into();
+        let mut best_score = context.evaluator.bounds(self).0;
 
-        for &i in move_order {
+        // The remembered best move first, then the usual order from the centre out
+        let first = self.table_move(context);
+        let move_order = context.move_order;
+        for i in first.into_iter().chain(move_order.iter().copied().filter(|&column| Some(column) != first)) {
             let result = self.run_unchecked(i);
             match result {
                 Some(GameState::InProgress) => {
-                    let (new_score, moves) = self.minimax_rec_inner_yellow(depth - 1, alpha.clone(), beta.clone(), move_order);
+                    let (new_score, moves) = match context.evaluator.exact(self) {
+                        Some(exact) => (exact, Vec::new()),
+                        None => self.minimax_rec_inner_yellow(context, depth - 1, alpha.clone(), beta.clone()),
+                    };
                     self.undo_unchecked();
 
                     if new_score > best_score {
@@ -226,83 +483,127 @@ impl Game {
                         best_moves = moves;
                         best_move = i;
                         alpha = alpha.max(new_score);
-                        if beta <= alpha { break }
+                        if beta <= alpha {
+                            context.cutoffs += 1;
+                            break;
+                        }
                     }
-                    // dbg!(&new_score, &best_score);
                 },
-                Some(GameState::Win(_)) => {
+                Some(GameState::Win(winner)) => {
+                    let best_score = context.evaluator.win(self, winner, 0);
                     self.undo_unchecked();
-                    let best_score = vec![i32::MAX - 1; self.k].into();
                     return (best_score, vec![i])
                 },
                 Some(GameState::Draw) => {
+                    let draw = context.evaluator.draw(self);
                     self.undo_unchecked();
-                    return (vec![0; self.k].into(), vec![i])
+                    return (draw, vec![i])
                 }
                 None => continue,
             }
         }
 
+        context.table.moves.insert(self.hash, best_move);
         best_moves.push(best_move);
 
-        // dbg!(&best_moves, &current_score);
         (best_score, best_moves)
     }
 
-    fn minimax_rec_inner_yellow(&mut self, depth: usize, alpha: Score, mut beta: Score, move_order: &[usize]) -> (Score, Vec<usize>) {
-        if depth == 0 {
-            return (self.last_score().clone(), Vec::new());
+    fn minimax_rec_inner_yellow<E: Evaluator>(&mut self, context: &mut Context<E>, depth: usize, alpha: E::Value, mut beta: E::Value) -> (E::Value, Vec<usize>) {
+        // The value does not matter once the search is aborted, the depth is thrown away
+        if depth == 0 || context.stopped(self.nodes) {
+            return (context.evaluator.evaluate(self), Vec::new());
         }
 
         let mut best_moves = Vec::new();
         let mut best_move = 0;
-        let mut best_score = vec![i32::MAX; self.k].into();
+        let mut best_score = context.evaluator.bounds(self).1;
 
-        for &i in move_order {
+        let first = self.table_move(context);
+        let move_order = context.move_order;
+        for i in first.into_iter().chain(move_order.iter().copied().filter(|&column| Some(column) != first)) {
             let result = self.run_unchecked(i);
             match result {
                 Some(GameState::InProgress) => {
-                    let (new_score, moves) = self.minimax_rec_inner_red(depth - 1, alpha.clone(), beta.clone(), move_order);
+                    let (new_score, moves) = match context.evaluator.exact(self) {
+                        Some(exact) => (exact, Vec::new()),
+                        None => self.minimax_rec_inner_red(context, depth - 1, alpha.clone(), beta.clone()),
+                    };
                     self.undo_unchecked();
-                    // dbg!(&new_score, &best_score);
                     if new_score < best_score {
                         best_score = new_score.clone();
                         best_moves = moves;
                         best_move = i;
                         beta = beta.min(new_score);
-                        if beta <= alpha { break }
+                        if beta <= alpha {
+                            context.cutoffs += 1;
+                            break;
+                        }
                     }
                 },
-                Some(GameState::Win(_)) => {
+                Some(GameState::Win(winner)) => {
+                    let best_score = context.evaluator.win(self, winner, 0);
                     self.undo_unchecked();
-                    let best_score = vec![i32::MIN + 1; self.k].into();
                     return (best_score, vec![i])
                 },
                 Some(GameState::Draw) => {
+                    let draw = context.evaluator.draw(self);
                     self.undo_unchecked();
-                    return (vec![0; self.k].into(), vec![i])
+                    return (draw, vec![i])
                 }
                 None => continue,
             }
         }
 
+        context.table.moves.insert(self.hash, best_move);
         best_moves.push(best_move);
 
-        // dbg!(&best_moves, &current_score);
         (best_score, best_moves)
     }
 
 
+    // Positions exactly `depth` moves ahead, lines where the game ends earlier are not counted
+    pub fn perft(&mut self, depth: usize) -> u64 {
+        if depth == 0 { return 1 }
+        if !matches!(self.game_state, GameState::InProgress) { return 0 }
+
+        let mut nodes = 0;
+        for column in 0..self.width {
+            if self.run_unchecked(column).is_none() { continue }
+            nodes += self.perft(depth - 1);
+            self.undo_unchecked();
+        }
+
+        nodes
+    }
+
+    // Perft split up by the first move
+    pub fn perft_divide(&mut self, depth: usize) -> Vec<(usize, u64)> {
+        if depth == 0 || !matches!(self.game_state, GameState::InProgress) { return Vec::new() }
+
+        let mut divide = Vec::new();
+        for column in 0..self.width {
+            if self.run_unchecked(column).is_none() { continue }
+            divide.push((column, self.perft(depth - 1)));
+            self.undo_unchecked();
+        }
+
+        divide
+    }
+
     pub fn run(&mut self, column: usize) -> Result<GameState, InsertError> {
         self.insert(column, self.turn())?;
         self.turn = self.turn.other();
+        self.history.play(column);
 
         Ok(self.game_state)
     }
 
-    fn run_unchecked(&mut self, column: usize) -> Option<GameState> {
+    // Plays a move without recording it in the history, None if the column is full
+    pub fn run_unchecked(&mut self, column: usize) -> Option<GameState> {
         self.insert_unchecked(column, self.turn())?;
         self.turn = self.turn.other();
+        self.nodes += 1;
 
         Some(self.game_state)
     }
@@ -313,11 +614,62 @@ impl Game {
         self.extract_unchecked(last_move);
         self.score_list.pop();
         self.game_state = GameState::InProgress; // This is techincally wrong, but I need it for minimax to work
+        self.history.back();
 
         Some(())
     }
+
+    // Replays the last undone move, or the last visited variation at this position
+    pub fn redo(&mut self) -> Option<GameState> {
+        let column = self.history.forward()?;
+        self.run(column).ok()
+    }
+
+    // Makes the line to the current position the main line of the history
+    pub fn promote(&mut self) {
+        self.history.promote(self.history.cursor());
+    }
+
+    // Switches to any position in the history, e.g. another variation
+    pub fn goto(&mut self, node: NodeId) {
+        while self.undo().is_some() {}
+        for column in self.history.path(node) {
+            let _ = self.run(column);
+        }
+    }
+
+    // Replays the history up to its cursor, failing on the first illegal move in any variation
+    pub fn from_history(m: usize, n: usize, k: usize, history: VariationTree) -> Result<Self, InsertError> {
+        let mut game = Self::new(m, n, k);
+        game.check_history(&history, VariationTree::ROOT)?;
+
+        for column in history.path(history.cursor()) {
+            game.run_unchecked(column);
+        }
+        game.history = history;
+
+        Ok(game)
+    }
+
+    fn check_history(&mut self, history: &VariationTree, node: NodeId) -> Result<(), InsertError> {
+        let children = history.node(node).children();
+        if !children.is_empty() && !matches!(self.game_state, GameState::InProgress) {
+            return Err(InsertError::GameOver);
+        }
+
+        for &child in children {
+            let column = history.node(child).column().unwrap();
+            self.insert(column, self.turn())?;
+            self.turn = self.turn.other();
+            let result = self.check_history(history, child);
+            self.undo_unchecked();
+            result?;
+        }
+
+        Ok(())
+    }
     
-    fn undo_unchecked(&mut self) {
+    pub fn undo_unchecked(&mut self) {
         let last_move = self.move_list.pop().unwrap();
         self.turn = self.turn.other();
         self.extract_unchecked(last_move);
@@ -342,6 +694,7 @@ impl Game {
         let score = self.score((column, full_spaces));
         self.score_list.push(score);
         self.board[self.height * column + full_spaces] = Some(color);
+        self.hash ^= zobrist(self.height * column + full_spaces, color);
         self.full_spaces[column] += 1;
         self.move_list.push(column);
         
@@ -354,23 +707,14 @@ impl Game {
         Some(full_spaces)
     }
 
-    fn extract(&mut self, column: usize) -> Result<(), ExtractError> {
-        if column >= self.width { return Err(ExtractError::InvalidColumn) }
-
-        let full_spaces = &mut self.full_spaces[column];
-        if full_spaces == &0 { return Err(ExtractError::ColumnEmpty) }
-
-        *full_spaces -= 1;
-        self.board[self.height * column + *full_spaces] = None;
-                
-        Ok(())
-    }
-
     fn extract_unchecked(&mut self, column: usize) {
         let full_spaces = &mut self.full_spaces[column];
 
         *full_spaces -= 1;
-        self.board[self.height * column + *full_spaces] = None;
+        let cell = self.height * column + *full_spaces;
+        if let Some(color) = self.board[cell].take() {
+            self.hash ^= zobrist(cell, color);
+        }
     }
     
     pub fn last_score(&self) -> &Score {
@@ -406,7 +750,8 @@ impl Game {
             .collect();
         let nw_se: Vec<&Option<Color>> = self.board.iter()
             .skip(self.height() * (x - nw) + y + nw)
-            .step_by(self.height() - 1).take(nw + 1 + se)
+            // A board of one row has no diagonals, and the single cell is taken with any step
+            .step_by((self.height() - 1).max(1)).take(nw + 1 + se)
             .collect();
 
         /* dbg!(north);
@@ -470,8 +815,7 @@ impl Game {
             score.0[count_other - 1] += 1;
         }
 
-        let mut tail = 0;
-        for head in self.k()..line.len() {
+        for (tail, head) in (self.k()..line.len()).enumerate() {
             let cell_head = line[head];
             let cell_tail = line[tail];
             // dbg!(cell_head);
@@ -501,19 +845,27 @@ impl Game {
             } else if count_turn == 0 {
                 score.0[count_other - 1] += 1;
             }
-
-            tail += 1;
         }
 
-        return score
+        score
     }
 
     pub fn serialize(&self) -> String {
         format!("{self}")
     }
 
+    pub fn display_with(&self, symbols: Symbols) -> DisplayWith<'_> {
+        DisplayWith { game: self, symbols }
+    }
+
+    // Detects the symbol set from the input, falling back to the default one
     pub fn deserialize(input: &str, k: usize) -> Result<Self, DeserializeError> {
-        use DeserializeError::{EmptyInput, DifferentWidths, BadSymbol, UnreachablePosition};
+        let symbols = Symbols::detect(input).unwrap_or_default();
+        Self::deserialize_with(input, k, symbols)
+    }
+
+    pub fn deserialize_with(input: &str, k: usize, symbols: Symbols) -> Result<Self, DeserializeError> {
+        use DeserializeError::{EmptyInput, DifferentWidths, BadSymbol, UnreachablePosition, TooSmall};
 
         let mut board = Vec::new();
         let mut width = None;
@@ -524,21 +876,15 @@ impl Game {
             if line.is_empty() { continue }
             let mut row = Vec::new();
             for symbol in line.chars() {
-                use Color::{Red, Yellow};
-
-                let field = match symbol {
-                    'X' => Some(Red),
-                    'O' => Some(Yellow),
-                    '_' => None,
-                    ' ' => continue,
-                    s => return Err(BadSymbol(s)),
+                if symbol.is_whitespace() { continue }
+                let Some(field) = symbols.field(symbol) else {
+                    return Err(BadSymbol(symbol))
                 };
                 row.push(field);
             }
 
             if let Some(width) = width {
                 if row.len() != width {
-                    dbg!(width, row.len());
                     return Err(DifferentWidths)
                 }
             } else {
@@ -551,7 +897,7 @@ impl Game {
         let Some(width) = width else { return Err(EmptyInput) };
 
         let height = board.len();
-
+        if width < k || height < k { return Err(TooSmall) }
 
         // Reconstruct the move list
         let mut move_list = Vec::new();
@@ -574,7 +920,7 @@ impl Game {
 
         for i in 0..width {
             if indexes[i] == height { continue }
-            if board[indexes[i]][i] != None {
+            if board[indexes[i]][i].is_some() {
                 return Err(UnreachablePosition)
             }
         }
@@ -582,22 +928,52 @@ impl Game {
         let mut board = Self::new(width, height, k);
 
         for i in move_list {
-            board.run_unchecked(i);
+            let _ = board.run(i);
         }
 
         Ok(board)
     }
 }
 
+// A random key per cell and colour, mixed from the index with splitmix64 instead of looked up
+fn zobrist(cell: usize, color: Color) -> u64 {
+    splitmix(2 * cell as u64 + color as u64 + 1)
+}
+
+// The hash of the empty board, so games of different sizes do not share positions in a move
+// table. The top bit keeps it apart from the cell keys.
+fn size_key(width: usize, height: usize, k: usize) -> u64 {
+    splitmix(1 << 63 | (width as u64) << 42 | (height as u64) << 21 | k as u64)
+}
+
+fn splitmix(key: u64) -> u64 {
+    let mut z = key.wrapping_mul(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
 impl Display for Game {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display_with(Symbols::default()))
+    }
+}
+
+pub struct DisplayWith<'a> {
+    game: &'a Game,
+    symbols: Symbols,
+}
+
+impl Display for DisplayWith<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         use Color::{Red, Yellow};
-        for row in 0..self.height {
-            for col in 0..self.width {
-                match self.board[self.height * (col + 1) - row - 1] {
-                    Some(Red) => write!(f, "X")?,
-                    Some(Yellow) => write!(f, "O")?,
-                    None => write!(f, "_")?,
+        let game = self.game;
+        for row in 0..game.height {
+            for col in 0..game.width {
+                match game.board[game.height * (col + 1) - row - 1] {
+                    Some(Red) => write!(f, "{}", self.symbols.red)?,
+                    Some(Yellow) => write!(f, "{}", self.symbols.yellow)?,
+                    None => write!(f, "{}", self.symbols.empty)?,
                 }
                 write!(f, " ")?;
             }
@@ -606,3 +982,168 @@ impl Display for Game {
         write!(f, "")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use super::*;
+    use crate::eval::ChainScore;
+
+    fn search(game: &mut Game, depth: usize, table: &mut MoveTable) -> Vec<usize> {
+        let (_, pv, _) = game.search_with(&ChainScore, depth, table, &SearchLimits::default(), |_, _, _, _| ControlFlow::Continue(()));
+        pv
+    }
+
+    fn search_limited(game: &mut Game, depth: usize, limits: &SearchLimits) -> (Score, Vec<usize>, SearchStats) {
+        game.search_with(&ChainScore, depth, &mut MoveTable::default(), limits, |_, _, _, _| ControlFlow::Continue(()))
+    }
+
+    fn position() -> Game {
+        let mut game = Game::new(7, 6, 4);
+        for column in [3, 3, 2] {
+            game.run(column).unwrap();
+        }
+        game
+    }
+
+    // The limited search has to end with the result of an unlimited search to its last depth
+    fn assert_last_depth(limits: SearchLimits, depth: usize) {
+        let (score, pv, stats) = search_limited(&mut position(), 10, &limits);
+        assert_eq!(stats.depth, depth);
+        let (full_score, full_pv, _) = search_limited(&mut position(), depth, &SearchLimits::default());
+        assert_eq!((score, pv), (full_score, full_pv));
+    }
+
+    #[test]
+    fn stop_flag_returns_the_first_depth() {
+        let stop = Arc::new(AtomicBool::new(true));
+        assert_last_depth(SearchLimits { stop: Some(stop), ..Default::default() }, 1);
+    }
+
+    #[test]
+    fn node_limit_returns_the_last_completed_depth() {
+        let mut nodes = Vec::new();
+        position().search_with(&ChainScore, 4, &mut MoveTable::default(), &SearchLimits::default(), |_, stats, _, _| {
+            nodes.push(stats.nodes);
+            ControlFlow::Continue(())
+        });
+
+        // One node more than the first three depths take stops in the fourth
+        assert_last_depth(SearchLimits { nodes: Some(nodes[2] + 1), ..Default::default() }, 3);
+    }
+
+    #[test]
+    fn time_limit_returns_the_last_completed_depth() {
+        // The clock is only read every 1024 nodes, so the first depths complete regardless
+        let (_, _, stats) = search_limited(&mut position(), 10, &SearchLimits { time: Some(Duration::ZERO), ..Default::default() });
+        assert!((2..10).contains(&stats.depth));
+        assert_last_depth(SearchLimits { time: Some(Duration::ZERO), ..Default::default() }, stats.depth);
+    }
+
+    #[test]
+    fn empty_boards_of_different_sizes_hash_differently() {
+        assert_ne!(Game::new(9, 6, 4).hash, Game::new(3, 3, 3).hash);
+        assert_ne!(Game::new(7, 6, 4).hash, Game::new(7, 6, 5).hash);
+
+        let mut game = Game::new(7, 6, 4);
+        game.run_unchecked(3);
+        game.undo_unchecked();
+        assert_eq!(game.hash, Game::new(7, 6, 4).hash);
+    }
+
+    #[test]
+    fn move_table_keeps_board_sizes_apart() {
+        let mut table = MoveTable::default();
+        let (mut wide, mut small) = (Game::new(9, 6, 4), Game::new(3, 3, 3));
+        search(&mut wide, 3, &mut table);
+        search(&mut small, 2, &mut table);
+        assert!(table.moves.contains_key(&wide.hash));
+        assert!(table.moves[&small.hash] < 3);
+    }
+
+    #[test]
+    fn table_moves_outside_the_board_are_ignored() {
+        let mut game = Game::new(3, 3, 3);
+        let mut table = MoveTable::default();
+        table.moves.insert(game.hash, 4);
+        let pv = search(&mut game, 2, &mut table);
+        assert!(pv.iter().all(|&column| column < 3));
+    }
+
+    fn from_history(moves: &str) -> Result<Game, InsertError> {
+        Game::from_history(7, 6, 4, VariationTree::parse(moves).unwrap())
+    }
+
+    #[test]
+    fn history_with_moves_after_a_win_is_rejected() {
+        assert!(matches!(from_history("1 2 1 2 1 2 1 2"), Err(InsertError::GameOver)));
+        assert!(matches!(from_history("1 2 1 2 1 2 1 (3) 2"), Err(InsertError::GameOver)));
+        // Red also wins in the variation of Yellow's last move
+        assert!(matches!(from_history("1 2 1 2 1 2 (3 1 1) 1"), Err(InsertError::GameOver)));
+        assert!(matches!(from_history("1 1 1 1 1 1 1"), Err(InsertError::ColumnFull)));
+        // A variation that takes back the winning move is fine
+        let game = from_history("1 2 1 2 1 2 1 (3 1)").unwrap();
+        assert!(matches!(game.game_state(), GameState::Win(Color::Red)));
+    }
+
+    #[test]
+    fn goto_switches_to_a_variation() {
+        let mut game = from_history("4 4 (3 2) 5").unwrap();
+        let history = game.history();
+        let variation = history.line_end(history.child(history.child(VariationTree::ROOT, 3).unwrap(), 2).unwrap());
+        game.goto(variation);
+
+        assert_eq!(game.move_list(), [3, 2, 1]);
+        assert_eq!(game.history().cursor(), variation);
+        assert_eq!(game.history().to_string(), "4 4 (3 2 *) 5");
+    }
+
+    #[test]
+    fn symbols_are_detected() {
+        assert_eq!(Symbols::detect("_ X\nO X"), Some(Symbols::XO));
+        assert_eq!(Symbols::detect(". R\nY R"), Some(Symbols::RY));
+        assert_eq!(Symbols::detect("- x\no x"), Some(Symbols::LOWERCASE));
+        // An empty board matches the first preset with its empty symbol
+        assert_eq!(Symbols::detect(". .\n. ."), Some(Symbols::RY));
+        assert_eq!(Symbols::detect("X Y"), None);
+    }
+
+    #[test]
+    fn grids_load_with_every_symbol_set() {
+        let mut expected = Game::new(4, 4, 3);
+        for column in [1, 1, 2] {
+            expected.run(column).unwrap();
+        }
+
+        for symbols in Symbols::PRESETS {
+            let grid = expected.display_with(symbols).to_string();
+            let game = Game::deserialize(&grid, 3).unwrap();
+            assert_eq!(game.to_string(), expected.to_string());
+            assert_eq!(game.move_list(), [1, 1, 2]);
+        }
+
+        let game = Game::deserialize(". . . .\n. . . .\n. Y . .\n. R R .", 3).unwrap();
+        assert_eq!(game.move_list(), [1, 1, 2]);
+        let game = Game::deserialize("- - - -\n- - - -\n- o - -\n- x x -", 3).unwrap();
+        assert_eq!(game.move_list(), [1, 1, 2]);
+        assert!(matches!(Game::deserialize("- - R -\n- x x -", 2), Err(DeserializeError::BadSymbol('-'))));
+    }
+
+    #[test]
+    fn perft_counts_every_line() {
+        let mut game = Game::new(7, 6, 4);
+        let counts: Vec<u64> = (1..=5).map(|depth| game.perft(depth)).collect();
+        assert_eq!(counts, [7, 49, 343, 2401, 16807]);
+        assert_eq!(game.move_list(), []);
+
+        // Red can complete a row on the fourth ply, which ends some lines early
+        let mut game = position();
+        assert!(game.perft(5) < 16807);
+        for depth in 1..=5 {
+            let divide = game.perft_divide(depth);
+            assert_eq!(divide.len(), 7);
+            assert_eq!(divide.iter().map(|&(_, nodes)| nodes).sum::<u64>(), game.perft(depth));
+        }
+    }
+}
@@ -0,0 +1,863 @@
+use std::{fmt::{self, Display, Formatter}, fs, path::PathBuf, time::Duration};
+
+use crate::{
+    bench::BenchOptions,
+    board::{Color, DeserializeError, Game, GameState, InsertError},
+    difficulty::Difficulty,
+    eval::{Evaluation, Weighted, DEFAULT_WEIGHTS},
+    mcts::{Limit, MctsOptions},
+    net::{ConnectOptions, Seat, ServeOptions},
+    selfplay::SelfPlayOptions,
+    tablebase::{Tablebase, TablebaseOptions},
+    tournament::TournamentOptions,
+    tune::TuneOptions,
+    Player,
+};
+
+pub const USAGE: &str = "\
+Usage: connect-mnk [OPTIONS]
+       connect-mnk tournament [OPTIONS]
+       connect-mnk selfplay [OPTIONS] --output <FILE>
+       connect-mnk engine
+       connect-mnk bench [OPTIONS]
+       connect-mnk serve [OPTIONS]
+       connect-mnk connect <ADDRESS> [OPTIONS]
+       connect-mnk tune --data <FILE> [OPTIONS]
+       connect-mnk tablebase [OPTIONS] --output <FILE>
+
+Options:
+  -m, --width <M>         Number of columns [default: 7]
+  -n, --height <N>        Number of rows [default: 6]
+  -k <K>                  Chips in a row required to win [default: 4]
+      --red <PLAYER>      Player for Red [default: human]
+      --yellow <PLAYER>   Player for Yellow [default: computer:10]
+      --position <FILE>   Start from the grid in FILE, which also sets the board size
+      --moves <MOVES>     Start after playing MOVES, 1-based columns such as 4453 or 4,4,5,3
+      --format <FORMAT>   Output format: ansi, text, svg, tikz or gif [default: ansi]
+      --output <FILE>     Write the svg, tikz or gif export of the finished game to FILE
+      --tui               Play in a full-screen terminal UI
+      --analysis          Show the evaluation of every column under the board on human turns
+      --verbose           Show the statistics of every depth the computer searches
+      --ponder            Let the computer search its expected position while a human thinks
+      --weights <FILE>    Evaluate with the weights written by tune, also for every subcommand
+                          [default: connect-mnk.weights if it exists]
+      --tablebase <FILE>  Play perfectly in positions of the table, also for every subcommand
+  -h, --help              Print this help
+
+Players:
+  human                   Moves are entered on the command line
+  computer[:OPTION]...    Minimax search, with options separated by colons:
+                            DEPTH for the search depth [default: 10]
+                            eval=EVALUATOR, one of default, chain, weighted or zugzwang, where
+                            default is weighted with loaded weights and chain without
+                            weights=FILE for weights written by tune, only for this player
+  mcts[:OPTION]...        Monte Carlo tree search, with options separated by colons:
+                            ITERATIONS or TIME such as 500ms or 2s [default: 10000]
+                            c=C for the exploration constant [default: 1.414]
+                            heuristic for rollouts guided by the chain score
+  LEVEL[:OPTION]...       Casual opponent that sometimes misses the best move, one of beginner,
+                          easy, medium or hard, with options separated by colons:
+                            depth=D for the search depth
+                            t=T for the temperature, higher values pick worse moves more often
+                            blunder=P for the chance of a random move
+                          Immediate wins are never missed. level is medium with options.
+";
+
+pub const TOURNAMENT_USAGE: &str = "\
+Usage: connect-mnk tournament [OPTIONS] --engine <PLAYER> --engine <PLAYER>...
+
+Options:
+  -m, --width <M>            Number of columns [default: 7]
+  -n, --height <N>           Number of rows [default: 6]
+  -k <K>                     Chips in a row required to win [default: 4]
+      --engine <PLAYER>      Add an engine, such as computer:6 or computer:6:eval=zugzwang
+      --gauntlet             Play the first engine against all others instead of a round robin
+      --rounds <N>           Openings per pairing, each played with both colours [default: 10]
+      --opening-moves <N>    Random moves of every opening [default: 2]
+      --seed <SEED>          Seed for the openings [default: 1]
+      --elo0 <ELO>           Elo difference of the SPRT null hypothesis [default: 0]
+      --elo1 <ELO>           Elo difference of the SPRT alternative hypothesis [default: 10]
+      --error-rate <P>       False positive and false negative rate of the SPRT [default: 0.05]
+  -h, --help                 Print this help
+";
+
+pub const SELFPLAY_USAGE: &str = "\
+Usage: connect-mnk selfplay [OPTIONS] --output <FILE>
+
+Writes every position of engine games as one JSON object per line, with the score vector,
+the search evaluation, the chosen move and the result of the game for Red.
+
+Options:
+  -m, --width <M>            Number of columns [default: 7]
+  -n, --height <N>           Number of rows [default: 6]
+  -k <K>                     Chips in a row required to win [default: 4]
+      --red <PLAYER>         Engine for Red [default: computer:6]
+      --yellow <PLAYER>      Engine for Yellow [default: computer:6]
+      --games <N>            Number of games [default: 100]
+      --opening-moves <N>    Random moves at the start of every game [default: 4]
+      --seed <SEED>          Seed for the openings [default: 1]
+      --output <FILE>        Dataset file to write
+  -h, --help                 Print this help
+";
+
+pub const ENGINE_USAGE: &str = "\
+Usage: connect-mnk engine
+
+Speaks a line-based engine protocol on stdin and stdout, send `help` for the commands.
+
+Options:
+  -h, --help                 Print this help
+";
+
+pub const BENCH_USAGE: &str = "\
+Usage: connect-mnk bench [OPTIONS]
+
+Runs perft and search on a fixed suite of positions, reporting nodes per second.
+Exits with an error if a perft result differs from the known one.
+
+Options:
+      --perft-depth <D>      Depth of perft [default: 6]
+      --search-depth <D>     Depth of the computer player's search [default: 8]
+      --divide               Show perft results for every first move
+      --verbose              Show the statistics of every search iteration
+  -h, --help                 Print this help
+";
+
+pub const SERVE_USAGE: &str = "\
+Usage: connect-mnk serve [OPTIONS]
+
+Hosts a game on the local network. The host validates every move, players who lose the
+connection can reclaim their seat with their token, and any further client watches.
+
+Options:
+  -m, --width <M>            Number of columns [default: 7]
+  -n, --height <N>           Number of rows [default: 6]
+  -k <K>                     Chips in a row required to win [default: 4]
+  -p, --port <PORT>          Port to listen on [default: 7777]
+      --red <SEAT>           Seat for Red [default: local]
+      --yellow <SEAT>        Seat for Yellow [default: remote]
+  -h, --help                 Print this help
+
+Seats:
+  local                      Moves are entered at the host's terminal
+  remote                     Moves come from a client that connects
+  computer[:DEPTH]           Minimax search running on the host
+  mcts[:OPTION]...           Monte Carlo tree search running on the host
+";
+
+pub const CONNECT_USAGE: &str = "\
+Usage: connect-mnk connect <ADDRESS> [OPTIONS]
+
+Joins a game hosted with `connect-mnk serve`, such as `connect-mnk connect 192.168.1.5:7777`.
+
+Options:
+      --seat <SEAT>          red, yellow or spectator [default: any free seat]
+      --player <PLAYER>      Who makes the moves, a player as in `connect-mnk --help` [default: human]
+      --token <TOKEN>        Reclaim a seat after losing the connection
+  -h, --help                 Print this help
+";
+
+pub const TUNE_USAGE: &str = "\
+Usage: connect-mnk tune --data <FILE> [OPTIONS]
+
+Tunes one evaluation weight per chain length by Texel tuning: the weights are changed one step
+at a time as long as they predict the game results of a selfplay dataset better. The default
+output file is loaded whenever connect-mnk starts in the same directory, other files with
+--weights or a computer player's weights= option.
+
+Options:
+      --data <FILE>          Dataset written by selfplay
+      --output <FILE>        Weights file to write [default: connect-mnk.weights]
+      --step <N>             Largest step tried for a weight [default: 64]
+      --passes <N>           Maximum number of passes over all weights [default: 1000]
+  -h, --help                 Print this help
+";
+
+pub const TABLEBASE_USAGE: &str = "\
+Usage: connect-mnk tablebase [OPTIONS] --output <FILE>
+
+Solves every position reachable from the start by retrograde analysis and writes the result
+and distance of each one. Complete tables are practical up to about 5x5, larger boards need
+--moves to start closer to the end. Load the written file with --tablebase.
+
+Options:
+  -m, --width <M>            Number of columns [default: 5]
+  -n, --height <N>           Number of rows [default: 4]
+  -k <K>                     Chips in a row required to win [default: 4]
+      --moves <MOVES>        Start after playing MOVES, 1-based columns such as 4453 or 4,4,5,3
+      --output <FILE>        Tablebase file to write
+  -h, --help                 Print this help
+";
+
+const DEFAULT_DEPTH: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Ansi,
+    Text,
+    Svg,
+    Tikz,
+    Gif,
+}
+
+pub struct Options {
+    pub width: usize,
+    pub height: usize,
+    pub k: usize,
+    pub red: Player,
+    pub yellow: Player,
+    pub position: Option<PathBuf>,
+    pub moves: Option<String>,
+    pub format: Format,
+    pub output: Option<PathBuf>,
+    pub tui: bool,
+    pub analysis: bool,
+    pub verbose: bool,
+    pub ponder: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: 7,
+            height: 6,
+            k: 4,
+            red: Player::Human,
+            yellow: Player::Computer(DEFAULT_DEPTH, Evaluation::Default),
+            position: None,
+            moves: None,
+            format: Format::Ansi,
+            output: None,
+            tui: false,
+            analysis: false,
+            verbose: false,
+            ponder: false,
+        }
+    }
+}
+
+pub enum Command {
+    Play(Options),
+    Tournament(TournamentOptions),
+    SelfPlay(SelfPlayOptions),
+    Engine,
+    Bench(BenchOptions),
+    Serve(ServeOptions),
+    Connect(ConnectOptions),
+    Tune(TuneOptions),
+    Tablebase(TablebaseOptions),
+    Help(&'static str),
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    UnknownArgument(String),
+    MissingValue(String),
+    BadNumber(String, String),
+    BadPlayer(String),
+    BadFormat(String),
+    BadSize,
+    ConflictingPosition,
+    ReadPosition(PathBuf, std::io::Error),
+    Deserialize(DeserializeError),
+    BadMoves(String),
+    IllegalMove(usize, InsertError),
+    GameOver(usize),
+    MissingOutput(&'static str),
+    TooFewEngines,
+    HumanEngine,
+    BadProbability(String),
+    BadSeat(String),
+    MissingAddress,
+    MissingData,
+    ReadWeights(PathBuf, std::io::Error),
+    ReadTablebase(PathBuf, std::io::Error),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use CliError::*;
+        match self {
+            UnknownArgument(argument) => write!(f, "unknown argument '{argument}'"),
+            MissingValue(option) => write!(f, "'{option}' requires a value"),
+            BadNumber(option, value) => write!(f, "'{value}' is not a valid number for '{option}'"),
+            BadPlayer(player) => write!(f, "'{player}' is not a player, expected human, computer[:OPTION]..., mcts[:OPTION]... or a level such as easy"),
+            BadFormat(format) => write!(f, "'{format}' is not a format, expected ansi, text, svg, tikz or gif"),
+            BadSize => write!(f, "the board must be at least k wide and k high, with k at least 1"),
+            ConflictingPosition => write!(f, "'--position' and '--moves' cannot be used together"),
+            ReadPosition(path, error) => write!(f, "could not read '{}': {error}", path.display()),
+            Deserialize(error) => write!(f, "could not parse the position: {error}"),
+            BadMoves(moves) => write!(f, "'{moves}' is not a move string, expected columns such as 4453 or 4,4,5,3"),
+            IllegalMove(number, InsertError::InvalidColumn) => write!(f, "move {number} is outside the board"),
+            IllegalMove(number, InsertError::ColumnFull) => write!(f, "move {number} is played in a full column"),
+            IllegalMove(number, InsertError::GameOver) => write!(f, "move {number} is played after the end of the game"),
+            GameOver(number) => write!(f, "move {number} is played after the game has ended"),
+            MissingOutput(option) => write!(f, "'{option}' requires '--output'"),
+            TooFewEngines => write!(f, "a tournament needs at least two engines"),
+            HumanEngine => write!(f, "engines must be computer players"),
+            BadProbability(value) => write!(f, "'{value}' is not a probability between 0 and 0.5"),
+            BadSeat(seat) => write!(f, "'{seat}' is not a seat"),
+            MissingAddress => write!(f, "'connect' requires the address of the host"),
+            MissingData => write!(f, "'tune' requires '--data'"),
+            ReadWeights(path, error) => write!(f, "could not load the weights from '{}': {error}", path.display()),
+            ReadTablebase(path, error) => write!(f, "could not load the tablebase from '{}': {error}", path.display()),
+        }
+    }
+}
+
+// Yields options with their inline value, accepting both `--option value` and `--option=value`
+struct Args<I> {
+    args: I,
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    fn next(&mut self) -> Option<(String, Option<String>)> {
+        let argument = self.args.next()?;
+        Some(match argument.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (argument, None),
+        })
+    }
+
+    fn value(&mut self, name: &str, inline: Option<String>) -> Result<String, CliError> {
+        inline.or_else(|| self.args.next()).ok_or_else(|| CliError::MissingValue(name.to_string()))
+    }
+}
+
+// Options that apply to all subcommands, loaded before the subcommand starts
+pub struct Globals {
+    pub weights: Option<Weighted>,
+    pub tablebase: Option<Tablebase>,
+}
+
+// Takes the global options out of the arguments, so they can be given anywhere. The weights
+// written by tune are also loaded without --weights if they are in the default file.
+pub fn globals(args: &mut Vec<String>) -> Result<Globals, CliError> {
+    let weights = take(args, "--weights")?
+        .or_else(|| Some(PathBuf::from(DEFAULT_WEIGHTS)).filter(|path| path.exists()))
+        .map(|path| Weighted::load(&path).map_err(|error| CliError::ReadWeights(path, error)))
+        .transpose()?;
+    let tablebase = take(args, "--tablebase")?
+        .map(|path| Tablebase::load(&path).map_err(|error| CliError::ReadTablebase(path, error)))
+        .transpose()?;
+    Ok(Globals { weights, tablebase })
+}
+
+fn take(args: &mut Vec<String>, name: &str) -> Result<Option<PathBuf>, CliError> {
+    let Some(index) = args.iter().position(|arg| arg == name || arg.strip_prefix(name).is_some_and(|rest| rest.starts_with('='))) else {
+        return Ok(None);
+    };
+    let argument = args.remove(index);
+    match argument.split_once('=') {
+        Some((_, path)) => Ok(Some(path.into())),
+        None if index < args.len() => Ok(Some(args.remove(index).into())),
+        None => Err(CliError::MissingValue(argument)),
+    }
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("tournament") => {
+            args.next();
+            return parse_tournament(Args { args });
+        },
+        Some("selfplay") => {
+            args.next();
+            return parse_selfplay(Args { args });
+        },
+        Some("bench") => {
+            args.next();
+            return parse_bench(Args { args });
+        },
+        Some("tablebase") => {
+            args.next();
+            return parse_tablebase(Args { args });
+        },
+        Some("tune") => {
+            args.next();
+            return parse_tune(Args { args });
+        },
+        Some("serve") => {
+            args.next();
+            return parse_serve(Args { args });
+        },
+        Some("connect") => {
+            args.next();
+            return parse_connect(Args { args });
+        },
+        Some("engine") => {
+            args.next();
+            return match args.next() {
+                None => Ok(Command::Engine),
+                Some(help) if help == "-h" || help == "--help" => Ok(Command::Help(ENGINE_USAGE)),
+                Some(argument) => Err(CliError::UnknownArgument(argument)),
+            };
+        },
+        _ => {},
+    }
+
+    let mut args = Args { args };
+    let mut options = Options::default();
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(USAGE)),
+            "-m" | "--width" => options.width = parse_number(&name, &args.value(&name, inline)?)?,
+            "-n" | "--height" => options.height = parse_number(&name, &args.value(&name, inline)?)?,
+            "-k" => options.k = parse_number(&name, &args.value(&name, inline)?)?,
+            "--red" => options.red = parse_player(&args.value(&name, inline)?)?,
+            "--yellow" => options.yellow = parse_player(&args.value(&name, inline)?)?,
+            "--position" => options.position = Some(args.value(&name, inline)?.into()),
+            "--moves" => options.moves = Some(args.value(&name, inline)?),
+            "--format" => options.format = parse_format(&args.value(&name, inline)?)?,
+            "--output" => options.output = Some(args.value(&name, inline)?.into()),
+            "--tui" => options.tui = true,
+            "--analysis" => options.analysis = true,
+            "--verbose" => options.verbose = true,
+            "--ponder" => options.ponder = true,
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    if options.position.is_some() && options.moves.is_some() {
+        return Err(CliError::ConflictingPosition);
+    }
+    if options.format == Format::Gif && options.output.is_none() {
+        return Err(CliError::MissingOutput("--format gif"));
+    }
+
+    Ok(Command::Play(options))
+}
+
+fn parse_tournament(mut args: Args<impl Iterator<Item = String>>) -> Result<Command, CliError> {
+    let mut options = TournamentOptions {
+        width: 7,
+        height: 6,
+        k: 4,
+        engines: Vec::new(),
+        gauntlet: false,
+        rounds: 10,
+        opening_moves: 2,
+        seed: 1,
+        elo0: 0.0,
+        elo1: 10.0,
+        error_rate: 0.05,
+    };
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(TOURNAMENT_USAGE)),
+            "-m" | "--width" => options.width = parse_number(&name, &args.value(&name, inline)?)?,
+            "-n" | "--height" => options.height = parse_number(&name, &args.value(&name, inline)?)?,
+            "-k" => options.k = parse_number(&name, &args.value(&name, inline)?)?,
+            "--engine" => options.engines.push(parse_engine(&args.value(&name, inline)?)?),
+            "--gauntlet" => options.gauntlet = true,
+            "--rounds" => options.rounds = parse_number(&name, &args.value(&name, inline)?)?,
+            "--opening-moves" => options.opening_moves = parse_number(&name, &args.value(&name, inline)?)?,
+            "--seed" => options.seed = parse_number(&name, &args.value(&name, inline)?)?,
+            "--elo0" => options.elo0 = parse_number(&name, &args.value(&name, inline)?)?,
+            "--elo1" => options.elo1 = parse_number(&name, &args.value(&name, inline)?)?,
+            "--error-rate" => {
+                let value = args.value(&name, inline)?;
+                options.error_rate = match value.parse() {
+                    Ok(rate) if rate > 0.0 && rate < 0.5 => rate,
+                    _ => return Err(CliError::BadProbability(value)),
+                };
+            },
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    if options.k == 0 || options.width < options.k || options.height < options.k {
+        return Err(CliError::BadSize);
+    }
+    if options.engines.len() < 2 {
+        return Err(CliError::TooFewEngines);
+    }
+
+    Ok(Command::Tournament(options))
+}
+
+fn parse_selfplay(mut args: Args<impl Iterator<Item = String>>) -> Result<Command, CliError> {
+    let mut options = SelfPlayOptions {
+        width: 7,
+        height: 6,
+        k: 4,
+        red: Player::Computer(6, Evaluation::Default),
+        yellow: Player::Computer(6, Evaluation::Default),
+        games: 100,
+        opening_moves: 4,
+        seed: 1,
+        output: PathBuf::new(),
+    };
+    let mut output = None;
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(SELFPLAY_USAGE)),
+            "-m" | "--width" => options.width = parse_number(&name, &args.value(&name, inline)?)?,
+            "-n" | "--height" => options.height = parse_number(&name, &args.value(&name, inline)?)?,
+            "-k" => options.k = parse_number(&name, &args.value(&name, inline)?)?,
+            "--red" => options.red = parse_engine(&args.value(&name, inline)?)?,
+            "--yellow" => options.yellow = parse_engine(&args.value(&name, inline)?)?,
+            "--games" => options.games = parse_number(&name, &args.value(&name, inline)?)?,
+            "--opening-moves" => options.opening_moves = parse_number(&name, &args.value(&name, inline)?)?,
+            "--seed" => options.seed = parse_number(&name, &args.value(&name, inline)?)?,
+            "--output" => output = Some(args.value(&name, inline)?.into()),
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    if options.k == 0 || options.width < options.k || options.height < options.k {
+        return Err(CliError::BadSize);
+    }
+    options.output = output.ok_or(CliError::MissingOutput("selfplay"))?;
+
+    Ok(Command::SelfPlay(options))
+}
+
+fn parse_bench(mut args: Args<impl Iterator<Item = String>>) -> Result<Command, CliError> {
+    let mut options = BenchOptions {
+        perft_depth: 6,
+        search_depth: 8,
+        divide: false,
+        verbose: false,
+    };
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(BENCH_USAGE)),
+            "--perft-depth" => options.perft_depth = parse_number(&name, &args.value(&name, inline)?)?,
+            "--search-depth" => options.search_depth = parse_number(&name, &args.value(&name, inline)?)?,
+            "--divide" => options.divide = true,
+            "--verbose" => options.verbose = true,
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    Ok(Command::Bench(options))
+}
+
+fn parse_tune(mut args: Args<impl Iterator<Item = String>>) -> Result<Command, CliError> {
+    let mut data = None;
+    let mut output = None;
+    let mut step = 64;
+    let mut max_passes = 1000;
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(TUNE_USAGE)),
+            "--data" => data = Some(args.value(&name, inline)?.into()),
+            "--output" => output = Some(args.value(&name, inline)?.into()),
+            "--step" => step = parse_number(&name, &args.value(&name, inline)?)?,
+            "--passes" => max_passes = parse_number(&name, &args.value(&name, inline)?)?,
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    Ok(Command::Tune(TuneOptions {
+        data: data.ok_or(CliError::MissingData)?,
+        output: output.unwrap_or_else(|| DEFAULT_WEIGHTS.into()),
+        step,
+        max_passes,
+    }))
+}
+
+fn parse_tablebase(mut args: Args<impl Iterator<Item = String>>) -> Result<Command, CliError> {
+    let mut options = TablebaseOptions {
+        width: 5,
+        height: 4,
+        k: 4,
+        moves: Vec::new(),
+        output: PathBuf::new(),
+    };
+    let mut output = None;
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(TABLEBASE_USAGE)),
+            "-m" | "--width" => options.width = parse_number(&name, &args.value(&name, inline)?)?,
+            "-n" | "--height" => options.height = parse_number(&name, &args.value(&name, inline)?)?,
+            "-k" => options.k = parse_number(&name, &args.value(&name, inline)?)?,
+            "--moves" => options.moves = parse_moves(&args.value(&name, inline)?)?,
+            "--output" => output = Some(args.value(&name, inline)?.into()),
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    if options.k == 0 || options.width < options.k || options.height < options.k {
+        return Err(CliError::BadSize);
+    }
+    options.output = output.ok_or(CliError::MissingOutput("tablebase"))?;
+
+    Ok(Command::Tablebase(options))
+}
+
+fn parse_serve(mut args: Args<impl Iterator<Item = String>>) -> Result<Command, CliError> {
+    let mut options = ServeOptions {
+        width: 7,
+        height: 6,
+        k: 4,
+        port: 7777,
+        red: Seat::Local,
+        yellow: Seat::Remote,
+    };
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(SERVE_USAGE)),
+            "-m" | "--width" => options.width = parse_number(&name, &args.value(&name, inline)?)?,
+            "-n" | "--height" => options.height = parse_number(&name, &args.value(&name, inline)?)?,
+            "-k" => options.k = parse_number(&name, &args.value(&name, inline)?)?,
+            "-p" | "--port" => options.port = parse_number(&name, &args.value(&name, inline)?)?,
+            "--red" => options.red = parse_seat(&args.value(&name, inline)?)?,
+            "--yellow" => options.yellow = parse_seat(&args.value(&name, inline)?)?,
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    if options.k == 0 || options.width < options.k || options.height < options.k {
+        return Err(CliError::BadSize);
+    }
+
+    Ok(Command::Serve(options))
+}
+
+fn parse_connect(mut args: Args<impl Iterator<Item = String>>) -> Result<Command, CliError> {
+    let mut options = ConnectOptions {
+        address: String::new(),
+        seat: None,
+        spectate: false,
+        player: Player::Human,
+        token: None,
+    };
+    let mut address = None;
+
+    while let Some((name, inline)) = args.next() {
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(CONNECT_USAGE)),
+            "--seat" => match args.value(&name, inline)?.as_str() {
+                "red" => options.seat = Some(Color::Red),
+                "yellow" => options.seat = Some(Color::Yellow),
+                "spectator" => options.spectate = true,
+                seat => return Err(CliError::BadSeat(seat.to_string())),
+            },
+            "--player" => options.player = parse_player(&args.value(&name, inline)?)?,
+            "--token" => options.token = Some(args.value(&name, inline)?),
+            _ if !name.starts_with('-') && address.is_none() => address = Some(name),
+            _ => return Err(CliError::UnknownArgument(name)),
+        }
+    }
+
+    options.address = address.ok_or(CliError::MissingAddress)?;
+    // Without a port the host's default is assumed
+    if !options.address.contains(':') {
+        options.address.push_str(":7777");
+    }
+
+    Ok(Command::Connect(options))
+}
+
+fn parse_seat(value: &str) -> Result<Seat, CliError> {
+    match value {
+        "local" => Ok(Seat::Local),
+        "remote" => Ok(Seat::Remote),
+        _ => match parse_player(value) {
+            Ok(Player::Human) | Err(_) => Err(CliError::BadSeat(value.to_string())),
+            Ok(engine) => Ok(Seat::Engine(engine)),
+        },
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::BadNumber(option.to_string(), value.to_string()))
+}
+
+pub fn parse_player(value: &str) -> Result<Player, CliError> {
+    match value.split_once(':') {
+        None if value == "human" => Ok(Player::Human),
+        None if value == "computer" => Ok(Player::Computer(DEFAULT_DEPTH, Evaluation::Default)),
+        Some(("computer", options)) => parse_computer(options),
+        None if value == "mcts" => Ok(Player::Mcts(MctsOptions::default())),
+        Some(("mcts", options)) => {
+            parse_mcts(options).map(Player::Mcts).ok_or_else(|| CliError::BadPlayer(value.to_string()))
+        },
+        _ => {
+            let (name, options) = value.split_once(':').unwrap_or((value, ""));
+            let level = if name == "level" { Difficulty::level("medium") } else { Difficulty::level(name) };
+            level.and_then(|level| parse_difficulty(level, options))
+                .map(Player::Difficulty)
+                .ok_or_else(|| CliError::BadPlayer(value.to_string()))
+        },
+    }
+}
+
+fn parse_computer(value: &str) -> Result<Player, CliError> {
+    let bad_player = || CliError::BadPlayer(format!("computer:{value}"));
+    let (mut depth, mut evaluation) = (DEFAULT_DEPTH, Evaluation::Default);
+    for option in value.split(':') {
+        if let Some(name) = option.strip_prefix("eval=") {
+            evaluation = Evaluation::parse(name).ok_or_else(bad_player)?;
+        } else if let Some(path) = option.strip_prefix("weights=") {
+            evaluation = Evaluation::load(path.into()).map_err(|error| CliError::ReadWeights(path.into(), error))?;
+        } else {
+            depth = option.parse().ok().filter(|&depth| depth > 0).ok_or_else(bad_player)?;
+        }
+    }
+    Ok(Player::Computer(depth, evaluation))
+}
+
+fn parse_difficulty(mut difficulty: Difficulty, value: &str) -> Option<Difficulty> {
+    for option in value.split(':').filter(|option| !option.is_empty()) {
+        let (name, value) = option.split_once('=')?;
+        match name {
+            "depth" => difficulty.depth = value.parse().ok().filter(|&depth| depth > 0)?,
+            "t" => difficulty.temperature = value.parse().ok().filter(|&t: &f64| t > 0.0)?,
+            "blunder" => difficulty.blunder_rate = value.parse().ok().filter(|p: &f64| (0.0..=1.0).contains(p))?,
+            _ => return None,
+        }
+    }
+    Some(difficulty)
+}
+
+fn parse_mcts(value: &str) -> Option<MctsOptions> {
+    let mut options = MctsOptions::default();
+    for option in value.split(':') {
+        if option == "heuristic" {
+            options.heuristic = true;
+        } else if let Some(exploration) = option.strip_prefix("c=") {
+            options.exploration = exploration.parse().ok().filter(|&c: &f64| c >= 0.0)?;
+        } else if let Some(millis) = option.strip_suffix("ms") {
+            options.limit = Limit::Time(Duration::from_millis(millis.parse().ok()?));
+        } else if let Some(seconds) = option.strip_suffix('s') {
+            options.limit = Limit::Time(Duration::from_secs_f64(seconds.parse().ok().filter(|&s: &f64| s > 0.0)?));
+        } else {
+            options.limit = Limit::Iterations(option.parse().ok().filter(|&iterations| iterations > 0)?);
+        }
+    }
+    Some(options)
+}
+
+fn parse_engine(value: &str) -> Result<Player, CliError> {
+    match parse_player(value)? {
+        Player::Human => Err(CliError::HumanEngine),
+        engine => Ok(engine),
+    }
+}
+
+fn parse_format(value: &str) -> Result<Format, CliError> {
+    match value {
+        "ansi" => Ok(Format::Ansi),
+        "text" => Ok(Format::Text),
+        "svg" => Ok(Format::Svg),
+        "tikz" => Ok(Format::Tikz),
+        "gif" => Ok(Format::Gif),
+        _ => Err(CliError::BadFormat(value.to_string())),
+    }
+}
+
+// Parses 1-based columns, either separated by commas or spaces, or as single digits
+pub fn parse_moves(moves: &str) -> Result<Vec<usize>, CliError> {
+    let bad_moves = || CliError::BadMoves(moves.to_string());
+
+    if moves.contains([',', ' ']) {
+        moves.split([',', ' '])
+            .filter(|column| !column.is_empty())
+            .map(|column| match column.parse::<usize>() {
+                Ok(column) if column > 0 => Ok(column - 1),
+                _ => Err(bad_moves()),
+            })
+            .collect()
+    } else {
+        moves.chars()
+            .map(|column| match column.to_digit(10) {
+                Some(column) if column > 0 => Ok(column as usize - 1),
+                _ => Err(bad_moves()),
+            })
+            .collect()
+    }
+}
+
+impl Options {
+    pub fn game(&self) -> Result<Game, CliError> {
+        if let Some(path) = &self.position {
+            let input = fs::read_to_string(path).map_err(|error| CliError::ReadPosition(path.clone(), error))?;
+            if self.k == 0 {
+                return Err(CliError::BadSize);
+            }
+            return Game::deserialize(&input, self.k).map_err(CliError::Deserialize);
+        }
+
+        if self.k == 0 || self.width < self.k || self.height < self.k {
+            return Err(CliError::BadSize);
+        }
+        let mut game = Game::new(self.width, self.height, self.k);
+
+        let moves = match &self.moves {
+            Some(moves) => parse_moves(moves)?,
+            None => Vec::new(),
+        };
+        for (i, column) in moves.into_iter().enumerate() {
+            if !matches!(game.game_state(), GameState::InProgress) {
+                return Err(CliError::GameOver(i + 1));
+            }
+            game.run(column).map_err(|error| CliError::IllegalMove(i + 1, error))?;
+        }
+
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Command, CliError> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    fn game(args: &str) -> Result<Game, CliError> {
+        match parse_str(args)? {
+            Command::Play(options) => options.game(),
+            _ => panic!("'{args}' is not a game"),
+        }
+    }
+
+    #[test]
+    fn one_cell_board_is_won_by_the_first_move() {
+        let game = game("-m 1 -n 1 -k 1 --moves 1").unwrap();
+        assert!(matches!(game.game_state(), GameState::Win(Color::Red)));
+    }
+
+    #[test]
+    fn bad_sizes_are_rejected() {
+        for args in ["-k 0", "-m 3 -k 4", "-n 3 -k 4", "-m 2 -n 2 -k 3"] {
+            assert!(matches!(game(args), Err(CliError::BadSize)), "{args}");
+        }
+        for args in ["tournament -m 4 -k 5 --engine computer --engine mcts", "selfplay -k 0 --output games.jsonl", "serve -n 2 -k 3"] {
+            assert!(matches!(parse_str(args), Err(CliError::BadSize)), "{args}");
+        }
+        assert!(matches!(parse_str("-m seven"), Err(CliError::BadNumber(..))));
+    }
+
+    #[test]
+    fn bad_moves_are_rejected() {
+        assert!(matches!(game("--moves 408"), Err(CliError::BadMoves(_))));
+        assert!(matches!(game("--moves 4,x"), Err(CliError::BadMoves(_))));
+        assert!(matches!(game("--moves 48"), Err(CliError::IllegalMove(2, InsertError::InvalidColumn))));
+        assert!(matches!(game("-m 4 -n 4 -k 4 --moves 11111"), Err(CliError::IllegalMove(5, InsertError::ColumnFull))));
+        assert!(matches!(game("--moves 12121213"), Err(CliError::GameOver(8))));
+        assert_eq!(parse_moves("4, 4,5 3").unwrap(), [3, 3, 4, 2]);
+    }
+
+    #[test]
+    fn unknown_arguments_are_rejected() {
+        assert!(matches!(parse_str("--colour red"), Err(CliError::UnknownArgument(argument)) if argument == "--colour"));
+        assert!(matches!(parse_str("--rules gravity"), Err(CliError::UnknownArgument(_))));
+        assert!(matches!(parse_str("tournament --seed 2 --fast"), Err(CliError::UnknownArgument(_))));
+        assert!(matches!(parse_str("engine go"), Err(CliError::UnknownArgument(_))));
+        assert!(matches!(parse_str("--red"), Err(CliError::MissingValue(_))));
+        assert!(matches!(parse_str("--red robot"), Err(CliError::BadPlayer(_))));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    board::{Color, Game, GameState},
+    eval::Weighted,
+    rng::Rng,
+};
+
+// Samples moves from a softmax over the root evaluations instead of always playing the best one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    pub depth: usize,
+    // In units of the weighted chain evaluation, where a chain of length one is worth 1, of
+    // length two 10 and so on
+    pub temperature: f64,
+    // Chance of a uniformly random move instead
+    pub blunder_rate: f64,
+}
+
+pub const LEVELS: [(&str, Difficulty); 4] = [
+    ("beginner", Difficulty { depth: 1, temperature: 100.0, blunder_rate: 0.3 }),
+    ("easy", Difficulty { depth: 2, temperature: 30.0, blunder_rate: 0.15 }),
+    ("medium", Difficulty { depth: 4, temperature: 10.0, blunder_rate: 0.05 }),
+    ("hard", Difficulty { depth: 6, temperature: 2.0, blunder_rate: 0.01 }),
+];
+
+impl Difficulty {
+    pub fn level(name: &str) -> Option<Self> {
+        LEVELS.iter().find(|(level, _)| *level == name).map(|&(_, difficulty)| difficulty)
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        LEVELS.iter().find(|(_, difficulty)| difficulty == self).map(|&(name, _)| name)
+    }
+
+    // The chosen move followed by the line the search expects, None if the game is over
+    pub fn choose(&self, game: &mut Game, rng: &mut Rng) -> Option<Vec<usize>> {
+        if !matches!(game.game_state(), GameState::InProgress) {
+            return None;
+        }
+
+        let evaluator = Weighted::for_k(game.k());
+        let sign = match game.turn() {
+            Color::Red => 1.0,
+            Color::Yellow => -1.0,
+        };
+
+        let mut lines = Vec::new();
+        let mut values = Vec::new();
+        for column in game.legal_moves() {
+            let (value, mut line) = match game.run_unchecked(column).unwrap() {
+                // Even the weakest level never misses an immediate win
+                GameState::Win(_) => {
+                    game.undo_unchecked();
+                    return Some(vec![column]);
+                },
+                GameState::Draw => (0, Vec::new()),
+                GameState::InProgress => game.minimax_with(&evaluator, self.depth.saturating_sub(1)),
+            };
+            game.undo_unchecked();
+
+            line.push(column);
+            line.reverse();
+            lines.push(line);
+            values.push(sign * value as f64);
+        }
+
+        if rng.next_f64() < self.blunder_rate {
+            return rng.choose(&lines).cloned();
+        }
+
+        let best = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = values.iter().map(|value| ((value - best) / self.temperature.max(f64::MIN_POSITIVE)).exp()).collect();
+        let mut sample = rng.next_f64() * weights.iter().sum::<f64>();
+        for (line, weight) in lines.iter().zip(&weights) {
+            if sample < *weight { return Some(line.clone()) }
+            sample -= weight;
+        }
+        lines.last().cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn immediate_wins_are_never_missed() {
+        let hottest = Difficulty { depth: 1, temperature: f64::MAX, blunder_rate: 1.0 };
+        let (_, beginner) = LEVELS[0];
+
+        // Red completes the first column, and Yellow the second one after Red plays elsewhere
+        for (moves, win) in [(&[0, 1, 0, 1, 0, 1][..], 0), (&[0, 1, 0, 1, 6, 1, 6][..], 1)] {
+            let mut game = Game::new(7, 6, 4);
+            for &column in moves {
+                game.run(column).unwrap();
+            }
+            for seed in 0..200 {
+                let mut rng = Rng::new(seed);
+                assert_eq!(hottest.choose(&mut game, &mut rng), Some(vec![win]));
+                assert_eq!(beginner.choose(&mut game, &mut rng), Some(vec![win]));
+            }
+            assert_eq!(game.move_list(), moves);
+        }
+    }
+}
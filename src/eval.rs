@@ -0,0 +1,303 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{
+    board::{Color, Game, GameState, MoveTable, Score, SearchLimits, SearchStats},
+    tablebase::{self, Probed, Tablebase},
+    zugzwang::Zugzwang,
+};
+
+// Weighted evaluations of this size correspond to odds of e to 1 in the tuner's model
+pub const SCALE: f64 = 100.0;
+
+// Wins score WIN less the ply the game ends at, so the search takes the fastest win and puts
+// off a loss for as long as it can. No game is longer than MAX_PLY, which keeps wins above every
+// chain count.
+const WIN: i32 = i32::MAX - 1;
+const MAX_PLY: i32 = 1 << 24;
+
+// Weights loaded at startup with --weights, or from DEFAULT_WEIGHTS if it exists
+static TUNED: OnceLock<Weighted> = OnceLock::new();
+
+// Where tune writes its weights unless told otherwise
+pub const DEFAULT_WEIGHTS: &str = "connect-mnk.weights";
+
+// Position evaluation for the minimax search, where Red maximises and Yellow minimises
+pub trait Evaluator {
+    type Value: Ord + Clone;
+
+    fn evaluate(&self, game: &Game) -> Self::Value;
+    // `plies` from the position until the winning move, 0 if the game is already won
+    fn win(&self, game: &Game, winner: Color, plies: usize) -> Self::Value;
+    fn draw(&self, game: &Game) -> Self::Value;
+    // Strictly below and above every value the other methods return
+    fn bounds(&self, game: &Game) -> (Self::Value, Self::Value);
+
+    // The result of the position if it is known without searching, such as from a tablebase
+    fn exact(&self, _game: &Game) -> Option<Self::Value> {
+        None
+    }
+}
+
+// The evaluator a computer player searches with, selected by name or by a weights file
+#[derive(Clone, Debug, Default)]
+pub enum Evaluation {
+    // The loaded weights if they fit the game, `ChainScore` otherwise
+    #[default]
+    Default,
+    Chain,
+    // The loaded weights if they fit the game, the default weights otherwise
+    Weighted,
+    Zugzwang,
+    // The player's own weights if they fit the game, the default weights otherwise
+    Weights(PathBuf, Weighted),
+}
+
+impl Evaluation {
+    pub const NAMES: &str = "default, chain, weighted or zugzwang";
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::Default),
+            "chain" => Some(Self::Chain),
+            "weighted" => Some(Self::Weighted),
+            "zugzwang" => Some(Self::Zugzwang),
+            _ => None,
+        }
+    }
+
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let weights = Weighted::load(&path)?;
+        Ok(Self::Weights(path, weights))
+    }
+}
+
+// As a player option
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "eval=default"),
+            Self::Chain => write!(f, "eval=chain"),
+            Self::Weighted => write!(f, "eval=weighted"),
+            Self::Zugzwang => write!(f, "eval=zugzwang"),
+            Self::Weights(path, _) => write!(f, "weights={}", path.display()),
+        }
+    }
+}
+
+// The open chain counts compared lexicographically, longest chains first
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChainScore;
+
+impl Evaluator for ChainScore {
+    type Value = Score;
+
+    fn evaluate(&self, game: &Game) -> Score {
+        game.last_score().clone()
+    }
+
+    fn win(&self, game: &Game, winner: Color, plies: usize) -> Score {
+        let value = WIN - (game.move_list().len() + plies) as i32;
+        match winner {
+            Color::Red => vec![value; game.k()].into(),
+            Color::Yellow => vec![-value; game.k()].into(),
+        }
+    }
+
+    fn draw(&self, game: &Game) -> Score {
+        vec![0; game.k()].into()
+    }
+
+    fn bounds(&self, game: &Game) -> (Score, Score) {
+        (vec![i32::MIN; game.k()].into(), vec![i32::MAX; game.k()].into())
+    }
+}
+
+// The open chain counts multiplied by one weight per chain length
+#[derive(Clone, Debug)]
+pub struct Weighted {
+    pub weights: Vec<i64>,
+}
+
+impl Weighted {
+    const WIN: i64 = i64::MAX / 2;
+
+    // Powers of ten, roughly what the lexicographic order of `ChainScore` does
+    pub fn new(k: usize) -> Self {
+        Self { weights: (0..k as u32).map(|length| 10i64.pow(length.min(12))).collect() }
+    }
+
+    // The loaded weights if they fit k, the default ones otherwise
+    pub fn for_k(k: usize) -> Self {
+        tuned(k).cloned().unwrap_or_else(|| Self::new(k))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let input = fs::read_to_string(path)?;
+        let line = input.lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or_else(|| io::Error::other("no weights found"))?;
+        let weights = line.strip_prefix("weights ")
+            .ok_or_else(|| io::Error::other("expected a line starting with 'weights'"))?
+            .split_whitespace()
+            .map(|weight| weight.parse().map_err(|_| io::Error::other(format!("'{weight}' is not a weight"))))
+            .collect::<io::Result<Vec<i64>>>()?;
+        Ok(Self { weights })
+    }
+
+    pub fn value(&self, score: &Score) -> i64 {
+        score.values().iter().zip(&self.weights).map(|(&count, weight)| count as i64 * weight).sum()
+    }
+
+    pub fn serialize(&self, source: &str) -> String {
+        let weights: Vec<String> = self.weights.iter().map(i64::to_string).collect();
+        format!("# Evaluation weights for k = {}, tuned on {source}\nweights {}\n", self.weights.len(), weights.join(" "))
+    }
+}
+
+pub fn set_tuned(weights: Weighted) {
+    let _ = TUNED.set(weights);
+}
+
+pub fn tuned(k: usize) -> Option<&'static Weighted> {
+    TUNED.get().filter(|tuned| tuned.weights.len() == k)
+}
+
+pub fn search(game: &mut Game, evaluation: &Evaluation, depth: usize) -> (Score, Vec<usize>) {
+    let (score, pv, _stats) = search_with_info(game, evaluation, depth, &SearchLimits::default(), |_, _, _| ControlFlow::Continue(()));
+    (score, pv)
+}
+
+// Iterative deepening with the evaluator, using the loaded tablebase if there is one and
+// reporting the chain score either way. `info` gets the statistics, score and principal variation
+// (best move last) of every depth.
+pub fn search_with_info(
+    game: &mut Game,
+    evaluation: &Evaluation,
+    depth: usize,
+    limits: &SearchLimits,
+    mut info: impl FnMut(&SearchStats, &Score, &[usize]) -> ControlFlow<()>,
+) -> (Score, Vec<usize>, SearchStats) {
+    let table = tablebase::loaded();
+    // Every search starts from an empty table, so its result depends only on the position
+    let mut moves = MoveTable::default();
+    let mut report = |game: &mut Game, stats: &SearchStats, pv: &[usize]| {
+        let (score, pv) = finish(game, table, pv);
+        info(stats, &score, &pv)
+    };
+
+    let k = game.k();
+    let search = Search { game: &mut *game, depth, moves: &mut moves, limits, table, report: &mut report };
+    let (pv, stats) = match (evaluation, tuned(k)) {
+        (Evaluation::Default | Evaluation::Weighted, Some(weights)) => search.run(weights),
+        (Evaluation::Default | Evaluation::Chain, _) => search.run(&ChainScore),
+        (Evaluation::Weighted, None) => search.run(&Weighted::new(k)),
+        (Evaluation::Zugzwang, _) => search.run(&Zugzwang),
+        (Evaluation::Weights(_, weights), _) if weights.weights.len() == k => search.run(weights),
+        (Evaluation::Weights(..), _) => search.run(&Weighted::new(k)),
+    };
+    let (score, pv) = finish(game, table, &pv);
+    (score, pv, stats)
+}
+
+// Everything a search needs besides the evaluator
+struct Search<'a, F> {
+    game: &'a mut Game,
+    depth: usize,
+    moves: &'a mut MoveTable,
+    limits: &'a SearchLimits,
+    table: Option<&'a Tablebase>,
+    report: &'a mut F,
+}
+
+impl<F: FnMut(&mut Game, &SearchStats, &[usize]) -> ControlFlow<()>> Search<'_, F> {
+    fn run<E: Evaluator>(self, evaluator: &E) -> (Vec<usize>, SearchStats) {
+        let Self { game, depth, moves, limits, table, report } = self;
+        let (_, pv, stats) = match table {
+            Some(table) => {
+                let evaluator = Probed { evaluator, table };
+                game.search_with(&evaluator, depth, moves, limits, |game, stats, _, pv| report(game, stats, pv))
+            },
+            None => game.search_with(evaluator, depth, moves, limits, |game, stats, _, pv| report(game, stats, pv)),
+        };
+        (pv, stats)
+    }
+}
+
+// The chain score at the end of a principal variation, which continues with the table's moves
+// where the search stopped at a position in the table
+fn finish(game: &mut Game, table: Option<&Tablebase>, pv: &[usize]) -> (Score, Vec<usize>) {
+    let mut line: Vec<usize> = pv.iter().rev().copied().collect();
+    if let Some(table) = table {
+        table.extend(game, &mut line);
+    }
+    (line_score(game, &line), line.into_iter().rev().collect())
+}
+
+impl Evaluator for Weighted {
+    type Value = i64;
+
+    fn evaluate(&self, game: &Game) -> i64 {
+        self.value(game.last_score())
+    }
+
+    fn win(&self, game: &Game, winner: Color, plies: usize) -> i64 {
+        let value = Self::WIN - (game.move_list().len() + plies) as i64;
+        match winner {
+            Color::Red => value,
+            Color::Yellow => -value,
+        }
+    }
+
+    fn draw(&self, _game: &Game) -> i64 {
+        0
+    }
+
+    fn bounds(&self, _game: &Game) -> (i64, i64) {
+        (i64::MIN, i64::MAX)
+    }
+}
+
+// The winner of a decided chain score and the ply the game ends at
+pub fn decided(score: &Score) -> Option<(Color, usize)> {
+    match *score.values().last()? {
+        value if value > WIN - MAX_PLY => Some((Color::Red, (WIN - value) as usize)),
+        value if value < -(WIN - MAX_PLY) => Some((Color::Yellow, (WIN + value) as usize)),
+        _ => None,
+    }
+}
+
+// Moves of the winner until a decided score's game ends, positive if the side to move wins
+pub fn mate_in(score: &Score, game: &Game) -> Option<isize> {
+    let (winner, ply) = decided(score)?;
+    let plies = ply.saturating_sub(game.move_list().len());
+    Some(if winner == game.turn() { plies.div_ceil(2) as isize } else { -((plies / 2) as isize) })
+}
+
+pub fn describe_mate(moves: isize) -> String {
+    if moves > 0 { format!("win in {moves}") } else { format!("loss in {}", -moves) }
+}
+
+// Chain score at the end of a line of moves, for players that do not search with `ChainScore`
+pub fn line_score(game: &mut Game, line: &[usize]) -> Score {
+    let mut state = game.game_state();
+    for &column in line {
+        state = game.run_unchecked(column).unwrap();
+    }
+
+    let score = match state {
+        GameState::Win(winner) => ChainScore.win(game, winner, 0),
+        GameState::Draw => ChainScore.draw(game),
+        GameState::InProgress => ChainScore.evaluate(game),
+    };
+    for _ in line {
+        game.undo_unchecked();
+    }
+    score
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::board::{Color, Game};
+
+// Global color table, padded to a power of two
+const PALETTE: [[u8; 3]; 8] = [
+    [0x1f, 0x4e, 0x9c], // Board
+    [0xff, 0xff, 0xff], // Empty hole
+    [0xd6, 0x28, 0x28], // Red
+    [0xf4, 0xc2, 0x0d], // Yellow
+    [0x2e, 0xcc, 0x40], // Winning line
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+];
+const BOARD: u8 = 0;
+const HOLE: u8 = 1;
+const RED: u8 = 2;
+const YELLOW: u8 = 3;
+const HIGHLIGHT: u8 = 4;
+const MIN_CODE_SIZE: u8 = 3;
+
+#[derive(Clone, Debug)]
+pub struct GifOptions {
+    pub cell_size: usize,
+    // Delays are in hundredths of a second
+    pub frame_delay: u16,
+    pub final_delay: u16,
+    pub repeat: bool,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 40,
+            frame_delay: 80,
+            final_delay: 400,
+            repeat: true,
+        }
+    }
+}
+
+// The screen size of a GIF is stored in 16 bits
+#[derive(Clone, Copy, Debug)]
+pub struct TooLarge {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Display for TooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "a {}x{} GIF is larger than the maximum of {max}x{max} pixels", self.width, self.height, max = u16::MAX)
+    }
+}
+
+// Renders one frame per move of the game's move list, starting from the empty board
+pub fn render(game: &Game, options: &GifOptions) -> Result<Vec<u8>, TooLarge> {
+    let width = game.width() * options.cell_size;
+    let height = game.height() * options.cell_size;
+    let (Ok(screen_width), Ok(screen_height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(TooLarge { width, height });
+    };
+    let mut encoder = Encoder::new(screen_width, screen_height, options.repeat);
+
+    let mut replay = Game::new(game.width(), game.height(), game.k());
+    let moves = game.move_list();
+    encoder.frame(&draw(&replay, options, false), options.frame_delay);
+
+    for (i, &column) in moves.iter().enumerate() {
+        let _ = replay.run(column);
+        let last = i + 1 == moves.len();
+        let delay = if last { options.final_delay } else { options.frame_delay };
+        encoder.frame(&draw(&replay, options, last), delay);
+    }
+
+    Ok(encoder.finish())
+}
+
+fn draw(game: &Game, options: &GifOptions, highlight: bool) -> Vec<u8> {
+    let cell = options.cell_size;
+    let width = game.width() * cell;
+    let mut pixels = vec![BOARD; width * game.height() * cell];
+
+    let radius = (cell * 2 / 5) as isize;
+    let ring = (cell / 10).max(1) as isize;
+    let winning_line = if highlight { game.winning_line().unwrap_or_default() } else { Vec::new() };
+
+    for column in 0..game.width() {
+        for row in 0..game.height() {
+            let index = match game.get(column, row) {
+                Some(Color::Red) => RED,
+                Some(Color::Yellow) => YELLOW,
+                None => HOLE,
+            };
+            let outline = winning_line.contains(&(column, row));
+            let cx = (column * cell + cell / 2) as isize;
+            let cy = ((game.height() - row - 1) * cell + cell / 2) as isize;
+
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let distance = dx * dx + dy * dy;
+                    if distance > radius * radius { continue }
+                    let inner = (radius - ring) * (radius - ring);
+                    let pixel = if outline && distance > inner { HIGHLIGHT } else { index };
+                    pixels[(cy + dy) as usize * width + (cx + dx) as usize] = pixel;
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+struct Encoder {
+    width: u16,
+    height: u16,
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn new(width: u16, height: u16, repeat: bool) -> Self {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"GIF89a");
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        // Global color table present, 3 bits of color resolution, 8 entries
+        bytes.extend_from_slice(&[0b1010_0010, BOARD, 0]);
+        for color in PALETTE {
+            bytes.extend_from_slice(&color);
+        }
+
+        if repeat {
+            bytes.extend_from_slice(&[0x21, 0xff, 0x0b]);
+            bytes.extend_from_slice(b"NETSCAPE2.0");
+            bytes.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+        }
+
+        Self { width, height, bytes }
+    }
+
+    fn frame(&mut self, pixels: &[u8], delay: u16) {
+        // Graphic control extension
+        self.bytes.extend_from_slice(&[0x21, 0xf9, 0x04, 0x00]);
+        self.bytes.extend_from_slice(&delay.to_le_bytes());
+        self.bytes.extend_from_slice(&[0x00, 0x00]);
+
+        // Image descriptor
+        self.bytes.extend_from_slice(&[0x2c, 0, 0, 0, 0]);
+        self.bytes.extend_from_slice(&self.width.to_le_bytes());
+        self.bytes.extend_from_slice(&self.height.to_le_bytes());
+        self.bytes.push(0x00);
+
+        self.bytes.push(MIN_CODE_SIZE);
+        for block in lzw(pixels).chunks(255) {
+            self.bytes.push(block.len() as u8);
+            self.bytes.extend_from_slice(block);
+        }
+        self.bytes.push(0x00);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.bytes.push(0x3b);
+        self.bytes
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    const MAX_CODE: u16 = 4095;
+    let clear = 1 << MIN_CODE_SIZE;
+    let end = clear + 1;
+
+    let mut writer = BitWriter { bytes: Vec::new(), buffer: 0, bits: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut width = MIN_CODE_SIZE as u32 + 1;
+    let mut hi = end;
+    let mut overflow = clear << 1;
+
+    writer.write(clear, width);
+
+    let Some((&first, rest)) = pixels.split_first() else {
+        writer.write(end, width);
+        return writer.finish();
+    };
+
+    let mut code = first as u16;
+    for &pixel in rest {
+        if let Some(&next) = table.get(&(code, pixel)) {
+            code = next;
+            continue;
+        }
+
+        writer.write(code, width);
+
+        hi += 1;
+        if hi == overflow {
+            width += 1;
+            overflow <<= 1;
+        }
+        if hi == MAX_CODE {
+            writer.write(clear, width);
+            table.clear();
+            width = MIN_CODE_SIZE as u32 + 1;
+            hi = end;
+            overflow = clear << 1;
+        } else {
+            table.insert((code, pixel), hi);
+        }
+        code = pixel as u16;
+    }
+
+    writer.write(code, width);
+    hi += 1;
+    if hi == overflow && width < 12 {
+        width += 1;
+    }
+    writer.write(end, width);
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    // A plain GIF decoder, returning the pixels, the number of clear codes and the widest code
+    fn decode(data: &[u8]) -> (Vec<u8>, usize, u32) {
+        let clear = 1 << MIN_CODE_SIZE;
+        let end = clear + 1;
+        let reset = || (0..=end).map(|code| vec![code as u8]).collect::<Vec<_>>();
+
+        let mut table = reset();
+        let mut width = MIN_CODE_SIZE as u32 + 1;
+        let (mut clears, mut widest) = (0, width);
+        let mut previous: Option<Vec<u8>> = None;
+        let mut pixels = Vec::new();
+        let mut position = 0;
+
+        loop {
+            let code = (0..width).fold(0, |code, bit| {
+                let index = position + bit as usize;
+                code | ((data[index / 8] >> (index % 8)) as usize & 1) << bit
+            });
+            position += width as usize;
+
+            if code == clear {
+                table = reset();
+                width = MIN_CODE_SIZE as u32 + 1;
+                clears += 1;
+                previous = None;
+                continue;
+            }
+            if code == end { break }
+
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) if code == table.len() => [&previous[..], &previous[..1]].concat(),
+                _ => panic!("code {code} is not in the table"),
+            };
+            pixels.extend(&entry);
+            if let Some(previous) = previous {
+                if table.len() < 4096 {
+                    table.push([&previous[..], &entry[..1]].concat());
+                }
+            }
+            if table.len() == 1 << width && width < 12 {
+                width += 1;
+                widest = widest.max(width);
+            }
+            previous = Some(entry);
+        }
+
+        (pixels, clears, widest)
+    }
+
+    #[test]
+    fn lzw_round_trip() {
+        assert_eq!(decode(&lzw(&[])).0, []);
+        assert_eq!(decode(&lzw(&[HIGHLIGHT])).0, [HIGHLIGHT]);
+
+        let run = vec![HOLE; 50_000];
+        assert_eq!(decode(&lzw(&run)).0, run);
+
+        // Random pixels fill the table quickly, so the encoder has to clear it
+        let mut rng = Rng::new(7);
+        let noise: Vec<u8> = (0..40_000).map(|_| rng.below(PALETTE.len()) as u8).collect();
+        let (pixels, clears, widest) = decode(&lzw(&noise));
+        assert_eq!(pixels, noise);
+        assert!(clears > 2);
+        assert_eq!(widest, 12);
+    }
+
+    #[test]
+    fn render_writes_a_complete_file() {
+        let mut game = Game::new(4, 4, 3);
+        for column in [0, 1, 0, 1, 0] {
+            game.run(column).unwrap();
+        }
+        let options = GifOptions { cell_size: 10, ..Default::default() };
+        let bytes = render(&game, &options).unwrap();
+
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(&bytes[6..10], [40, 0, 40, 0]);
+        assert_eq!(bytes[10], 0b1010_0010);
+        assert_eq!(&bytes[13..16], PALETTE[0]);
+        assert_eq!(bytes.last(), Some(&0x3b));
+
+        // Walk the blocks after the color table, decoding every frame
+        let sub_blocks = |position: &mut usize| {
+            let mut data = Vec::new();
+            while bytes[*position] != 0 {
+                let length = bytes[*position] as usize;
+                data.extend(&bytes[*position + 1..*position + 1 + length]);
+                *position += 1 + length;
+            }
+            *position += 1;
+            data
+        };
+        let mut position = 13 + 3 * PALETTE.len();
+        let mut frames = Vec::new();
+        loop {
+            match bytes[position] {
+                0x21 => {
+                    position += 2;
+                    sub_blocks(&mut position);
+                },
+                0x2c => {
+                    assert_eq!(bytes[position + 10], MIN_CODE_SIZE);
+                    position += 11;
+                    frames.push(decode(&sub_blocks(&mut position)).0);
+                },
+                0x3b => break,
+                byte => panic!("unexpected block {byte:#x}"),
+            }
+        }
+
+        assert_eq!(position, bytes.len() - 1);
+        assert_eq!(frames.len(), 6);
+        assert!(frames.iter().all(|frame| frame.len() == 40 * 40));
+        assert!(!frames[4].contains(&HIGHLIGHT));
+        assert!(frames[5].contains(&HIGHLIGHT));
+    }
+}
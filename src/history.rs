@@ -0,0 +1,291 @@
+use std::fmt::{self, Display, Formatter};
+
+pub type NodeId = usize;
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    column: Option<usize>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    // Child followed by redo, the most recently visited one
+    last_child: Option<NodeId>,
+}
+
+impl Node {
+    // None for the root
+    pub fn column(&self) -> Option<usize> {
+        self.column
+    }
+
+    // The first child is the main line, the others are variations
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+#[derive(Debug)]
+pub enum HistoryError {
+    BadToken(String),
+    VariationAtRoot,
+    UnbalancedParentheses,
+}
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::BadToken(token) => write!(f, "'{token}' is not a column"),
+            HistoryError::VariationAtRoot => write!(f, "a variation must follow a move"),
+            HistoryError::UnbalancedParentheses => write!(f, "unbalanced parentheses"),
+        }
+    }
+}
+
+// Tree of every line played from the starting position, with a cursor at the current position
+#[derive(Clone, Debug)]
+pub struct VariationTree {
+    nodes: Vec<Node>,
+    cursor: NodeId,
+}
+
+impl Default for VariationTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VariationTree {
+    pub const ROOT: NodeId = 0;
+
+    pub fn new() -> Self {
+        let root = Node { column: None, parent: None, children: Vec::new(), last_child: None };
+        Self { nodes: vec![root], cursor: Self::ROOT }
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn cursor(&self) -> NodeId {
+        self.cursor
+    }
+
+    // The child of the node that plays this column
+    pub fn child(&self, id: NodeId, column: usize) -> Option<NodeId> {
+        self.nodes[id].children.iter()
+            .copied()
+            .find(|&child| self.nodes[child].column == Some(column))
+    }
+
+    // Moves the cursor to the child with this column, adding it as a new variation if needed
+    pub fn play(&mut self, column: usize) -> NodeId {
+        let child = self.child(self.cursor, column).unwrap_or_else(|| {
+            let id = self.nodes.len();
+            self.nodes.push(Node { column: Some(column), parent: Some(self.cursor), children: Vec::new(), last_child: None });
+            self.nodes[self.cursor].children.push(id);
+            id
+        });
+
+        self.nodes[self.cursor].last_child = Some(child);
+        self.cursor = child;
+        child
+    }
+
+    // Moves the cursor to the parent, returning the column of the move taken back
+    pub fn back(&mut self) -> Option<usize> {
+        let node = &self.nodes[self.cursor];
+        let parent = node.parent?;
+        let column = node.column;
+        self.cursor = parent;
+        column
+    }
+
+    // The column redo would play, following the most recently visited child
+    pub fn forward(&self) -> Option<usize> {
+        let child = self.nodes[self.cursor].last_child?;
+        self.nodes[child].column
+    }
+
+    // Columns from the root to the node
+    pub fn path(&self, mut id: NodeId) -> Vec<usize> {
+        let mut path = Vec::new();
+        while let Some(column) = self.nodes[id].column {
+            path.push(column);
+            id = self.nodes[id].parent.unwrap();
+        }
+        path.reverse();
+        path
+    }
+
+    // Columns of the alternatives to the current position's next move
+    pub fn variations(&self) -> Vec<usize> {
+        self.nodes[self.cursor].children.iter()
+            .filter_map(|&child| self.nodes[child].column)
+            .collect()
+    }
+
+    // Makes the node's line the main line at every branch above it
+    pub fn promote(&mut self, mut id: NodeId) {
+        while let Some(parent) = self.nodes[id].parent {
+            let children = &mut self.nodes[parent].children;
+            let index = children.iter().position(|&child| child == id).unwrap();
+            children[..=index].rotate_right(1);
+            id = parent;
+        }
+    }
+
+    // Follows the main line of every branch below the node to its last move
+    pub fn line_end(&self, mut id: NodeId) -> NodeId {
+        while let Some(&child) = self.nodes[id].children.first() {
+            id = child;
+        }
+        id
+    }
+
+    pub fn main_line_end(&self) -> NodeId {
+        self.line_end(Self::ROOT)
+    }
+
+    // Parses 1-based columns with variations in parentheses after the move they replace,
+    // e.g. `4 4 (3 2) 5`. The cursor is placed at a `*` after a move, or at the end of the main
+    // line without one.
+    pub fn parse(input: &str) -> Result<Self, HistoryError> {
+        let mut tree = Self::new();
+        let mut stack = Vec::new();
+        let mut marked = None;
+
+        let spaced = input.replace('(', " ( ").replace(')', " ) ");
+        for token in spaced.split_whitespace() {
+            match token {
+                "(" => {
+                    stack.push(tree.cursor);
+                    tree.cursor = tree.nodes[tree.cursor].parent.ok_or(HistoryError::VariationAtRoot)?;
+                },
+                ")" => tree.cursor = stack.pop().ok_or(HistoryError::UnbalancedParentheses)?,
+                "*" => marked = Some(tree.cursor),
+                column => match column.parse::<usize>() {
+                    Ok(column) if column > 0 => { tree.play(column - 1); },
+                    _ => return Err(HistoryError::BadToken(column.to_string())),
+                },
+            }
+        }
+
+        if !stack.is_empty() {
+            return Err(HistoryError::UnbalancedParentheses);
+        }
+
+        // Redo follows the main line after parsing
+        for node in &mut tree.nodes {
+            node.last_child = node.children.first().copied();
+        }
+        tree.cursor = marked.unwrap_or_else(|| tree.main_line_end());
+        Ok(tree)
+    }
+
+    fn write_line(&self, f: &mut Formatter<'_>, id: NodeId, first: &mut bool) -> fmt::Result {
+        let separator = |f: &mut Formatter<'_>, first: &mut bool| {
+            if !*first { write!(f, " ")?; }
+            *first = false;
+            Ok(())
+        };
+
+        // The cursor is only marked where parse would not put it anyway
+        if id == self.cursor && id != self.main_line_end() {
+            separator(f, first)?;
+            write!(f, "*")?;
+        }
+
+        let Some((&main, variations)) = self.nodes[id].children.split_first() else { return Ok(()) };
+
+        separator(f, first)?;
+        write!(f, "{}", self.nodes[main].column.unwrap() + 1)?;
+
+        for &variation in variations {
+            separator(f, first)?;
+            write!(f, "({}", self.nodes[variation].column.unwrap() + 1)?;
+            self.write_line(f, variation, &mut false)?;
+            write!(f, ")")?;
+        }
+
+        self.write_line(f, main, first)
+    }
+}
+
+// The format read by parse
+impl Display for VariationTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.write_line(f, Self::ROOT, &mut true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(input: &str) -> String {
+        VariationTree::parse(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for input in ["", "4", "4 4 (3 2) 5", "4 4 (3 2 (1) 6) 5 (6 7) 2", "4 * 4 (3 2) 5", "4 4 (3 * 2) 5"] {
+            assert_eq!(round_trip(input), input);
+        }
+        // The cursor at the end of the main line is where parse puts it anyway
+        assert_eq!(round_trip("4 4 (3 2) 5 *"), "4 4 (3 2) 5");
+        assert_eq!(round_trip("4 4(3 2)5"), "4 4 (3 2) 5");
+    }
+
+    #[test]
+    fn parse_places_the_cursor() {
+        let tree = VariationTree::parse("4 4 (3 * 2) 5").unwrap();
+        assert_eq!(tree.path(tree.cursor()), [3, 2]);
+        let tree = VariationTree::parse("4 4 (3 2) 5").unwrap();
+        assert_eq!(tree.path(tree.cursor()), [3, 3, 4]);
+        assert_eq!(tree.forward(), None);
+    }
+
+    #[test]
+    fn bad_histories_are_rejected() {
+        assert!(matches!(VariationTree::parse("4 0"), Err(HistoryError::BadToken(_))));
+        assert!(matches!(VariationTree::parse("4 x"), Err(HistoryError::BadToken(_))));
+        assert!(matches!(VariationTree::parse("(4)"), Err(HistoryError::VariationAtRoot)));
+        assert!(matches!(VariationTree::parse("4 (3"), Err(HistoryError::UnbalancedParentheses)));
+        assert!(matches!(VariationTree::parse("4 3)"), Err(HistoryError::UnbalancedParentheses)));
+    }
+
+    #[test]
+    fn playing_another_move_adds_a_variation() {
+        let mut tree = VariationTree::new();
+        for column in [3, 3, 4] {
+            tree.play(column);
+        }
+        let main = tree.cursor();
+        assert_eq!(tree.back(), Some(4));
+        assert_eq!(tree.back(), Some(3));
+        tree.play(2);
+        tree.back();
+
+        assert_eq!(tree.variations(), [3, 2]);
+        // Redo follows the variation visited last
+        assert_eq!(tree.forward(), Some(2));
+        assert_eq!(tree.to_string(), "4 * 4 (3) 5");
+
+        // Playing a known move follows it instead of adding it again
+        tree.play(3);
+        tree.play(4);
+        assert_eq!(tree.cursor(), main);
+        assert_eq!(tree.main_line_end(), main);
+    }
+
+    #[test]
+    fn promote_makes_the_line_the_main_line() {
+        let mut tree = VariationTree::parse("4 4 (3 2 (1 *)) 5").unwrap();
+        let variation = tree.cursor();
+        tree.promote(variation);
+
+        assert_eq!(tree.to_string(), "4 3 (4 5) 1 (2)");
+        assert_eq!(tree.main_line_end(), variation);
+        let old_main = tree.child(tree.child(VariationTree::ROOT, 3).unwrap(), 3).unwrap();
+        assert_eq!(tree.path(tree.line_end(old_main)), [3, 3, 4]);
+    }
+}
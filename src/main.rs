@@ -1,111 +1,518 @@
-#[allow(dead_code)]
-
 mod board;
+mod history;
+mod eval;
+mod svg;
+mod gif;
+mod tikz;
+mod ansi;
+mod cli;
+mod rng;
+mod tournament;
+mod selfplay;
+mod protocol;
+mod tui;
+mod bench;
+mod net;
+mod zugzwang;
+mod mcts;
+mod difficulty;
+mod tune;
+mod tablebase;
+mod analysis;
+mod ponder;
 use board::*;
+use history::VariationTree;
+use ansi::Ansi;
+use cli::{Command, Format, Options};
 
-use std::io::stdin;
+use std::{env, fmt::{self, Display, Formatter}, fs, io::stdin, ops::ControlFlow, process::exit};
 use std::num::ParseIntError;
 
 fn main() {
-    let input = "
-        _ _ _ _ _ _ _
-        _ _ _ _ _ _ _
-        _ _ _ _ _ _ _
-        _ _ _ X _ _ _
-        _ _ X O _ _ _
-        _ _ O X X O _
-    ";
-
-    let mut board = Game::deserialize(input, 4).unwrap();
-    
-    println!("{board}");
-    
-    let p1 = Player::Computer(10);
-    let p2 = Player::Computer(10);
-
-    play(&mut board, &p1, &p2);
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let globals = cli::globals(&mut args).unwrap_or_else(|error| fail(error));
+    if let Some(weights) = globals.weights {
+        eval::set_tuned(weights);
+    }
+    if let Some(table) = globals.tablebase {
+        tablebase::set_loaded(table);
+    }
+
+    let options = match cli::parse(args) {
+        Ok(Command::Play(options)) => options,
+        Ok(Command::Tournament(options)) => {
+            tournament::run(&options);
+            return;
+        },
+        Ok(Command::SelfPlay(options)) => {
+            if let Err(error) = selfplay::run(&options) {
+                eprintln!("error: could not write '{}': {error}", options.output.display());
+                exit(1);
+            }
+            return;
+        },
+        Ok(Command::Engine) => {
+            protocol::run();
+            return;
+        },
+        Ok(Command::Bench(options)) => {
+            if !bench::run(&options) {
+                exit(1);
+            }
+            return;
+        },
+        Ok(Command::Serve(options)) => {
+            if let Err(error) = net::serve(&options) {
+                eprintln!("error: {error}");
+                exit(1);
+            }
+            return;
+        },
+        Ok(Command::Connect(options)) => {
+            if let Err(error) = net::connect(&options) {
+                eprintln!("error: could not connect to '{}': {error}", options.address);
+                exit(1);
+            }
+            return;
+        },
+        Ok(Command::Tune(options)) => {
+            if let Err(error) = tune::run(&options) {
+                eprintln!("error: {error}");
+                exit(1);
+            }
+            return;
+        },
+        Ok(Command::Tablebase(options)) => {
+            if let Err(error) = tablebase::run(&options) {
+                eprintln!("error: {error}");
+                exit(1);
+            }
+            return;
+        },
+        Ok(Command::Help(usage)) => {
+            print!("{usage}");
+            return;
+        },
+        Err(error) => fail(error),
+    };
+
+    let mut board = options.game().unwrap_or_else(|error| fail(error));
+
+    if options.tui {
+        if let Err(error) = tui::play(&mut board, &options.red, &options.yellow) {
+            eprintln!("error: {error}");
+            exit(1);
+        }
+    } else {
+        show(&board, options.format);
+        play(&mut board, &options);
+    }
+
+    if let Err(error) = export(&board, &options) {
+        eprintln!("error: could not write the export: {error}");
+        exit(1);
+    }
+}
+
+fn fail(error: impl Display) -> ! {
+    eprintln!("error: {error}\n\nFor more information, try '--help'.");
+    exit(2);
 }
 
+fn show(board: &Game, format: Format) {
+    match format {
+        Format::Text => println!("{board}"),
+        _ => println!("{}", Ansi::auto(board)),
+    }
+}
+
+fn export(board: &Game, options: &Options) -> std::io::Result<()> {
+    let bytes = match options.format {
+        Format::Ansi | Format::Text => return Ok(()),
+        Format::Svg => svg::render(board, &Default::default()).into_bytes(),
+        Format::Tikz => tikz::render(board, &Default::default()).into_bytes(),
+        Format::Gif => gif::render(board, &Default::default())
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error.to_string()))?,
+    };
+
+    match &options.output {
+        Some(path) => fs::write(path, bytes),
+        None => {
+            print!("{}", String::from_utf8_lossy(&bytes));
+            Ok(())
+        },
+    }
+}
+
+#[derive(Clone)]
 enum Player {
     Human,
-    Computer(usize) // Search depth
+    Computer(usize, eval::Evaluation), // Search depth
+    Mcts(mcts::MctsOptions),
+    Difficulty(difficulty::Difficulty),
 }
 
-fn play(board: &mut Game, p1: &Player, p2: &Player) {
+const DEFAULT_HINT_DEPTH: usize = 10;
+
+const COMMANDS: &str = "\
+Commands:
+  <column>      Drop a chip into the column
+  u, undo       Take back your last move
+  redo          Replay the moves taken back by undo
+  hint          Suggest a move
+  eval          Evaluate the position
+  pv            Show the principal variation
+  analyse       Show the evaluation and principal variation of every column
+  threats       Show the threats by row parity and the predicted zugzwang outcome
+  moves         Show the moves played and the legal columns
+  variations    Show the moves played from this position in the main line and its variations
+  variation <c> Switch to the variation that continues with column c here
+  promote       Make the current line the main line
+  save <file>   Save the position and its variations to a file
+  load <file>   Load a position from a file
+  swap          Swap the players of Red and Yellow
+  depth <n>     Set the search depth of the computer and of hints
+  show          Show the board
+  help          Show this help
+  q, quit       Quit the game";
+
+fn play(board: &mut Game, options: &Options) {
+    let format = options.format;
+    let mut players = [options.red.clone(), options.yellow.clone()];
+    // Hints search like the computer player
+    let (mut hint_depth, hint_evaluation) = players.iter()
+        .find_map(|player| match player {
+            Player::Computer(depth, evaluation) => Some((*depth, evaluation.clone())),
+            Player::Human | Player::Mcts(_) | Player::Difficulty(_) => None,
+        })
+        .unwrap_or((DEFAULT_HINT_DEPTH, Default::default()));
+    // The analysis is shown once per position, not again after every command
+    let mut analysed = None;
+    let mut expected: Option<ponder::Expected> = None;
+    let mut ponder: Option<ponder::Ponder> = None;
+
     loop {
         let turn = board.turn();
         println!("{turn}'s turn");
         let player = match turn {
-            Color::Red => p1,
-            Color::Yellow => p2,  
+            Color::Red => players[0].clone(),
+            Color::Yellow => players[1].clone(),
         };
-        match player {
+        let column = match player {
             Player::Human => {
-                match parse_input(board.width()) {
-                    Ok(PlayerInput::Column(column)) => {
-                        let state = board.run(column - 1);
-                        println!("{board}");
+                if options.analysis && analysed.as_deref() != Some(board.move_list()) {
+                    print!("{}", analysis::analyse(board, &hint_evaluation, hint_depth));
+                    analysed = Some(board.move_list().to_vec());
+                }
+                let opponent = match turn {
+                    Color::Red => &players[1],
+                    Color::Yellow => &players[0],
+                };
+                if let (true, None, Player::Computer(depth, evaluation), Some(expected)) = (options.ponder, &ponder, opponent, &expected) {
+                    ponder = ponder::Ponder::start(board, expected, evaluation, *depth);
+                }
+
+                let input = parse_input(board.width());
+                // Only the expected move keeps the search. Other commands may search themselves
+                // or change the position.
+                let hit = matches!((&input, &expected), (Ok(PlayerInput::Column(column)), Some(expected)) if expected.is_reply(column - 1));
+                if !hit {
+                    if let Some(ponder) = ponder.take() { ponder.cancel() }
+                }
+                match input {
+                    Ok(PlayerInput::Column(column)) => column - 1,
+                    Ok(PlayerInput::Undo) => {
+                        board.undo();
+                        board.undo();
+                        show(board, format);
+                        println!("{:?}", board.last_score());
+                        continue;
+                    },
+                    Ok(PlayerInput::Redo) => {
+                        board.redo();
+                        board.redo();
+                        show(board, format);
                         println!("{:?}", board.last_score());
-                        match state {
-                            Ok(GameState::InProgress) => {},
-                            Ok(GameState::Win(winner)) => {
-                                println!("{winner} wins!");
-                                break;
+                        continue;
+                    },
+                    Ok(PlayerInput::Hint) => {
+                        if let Some((column, value)) = tablebase::loaded().and_then(|table| table.best_move(board)) {
+                            println!("Hint: column {} ({value})", column + 1);
+                            continue;
+                        }
+                        let (score, move_list) = eval::search(board, &hint_evaluation, hint_depth);
+                        match eval::mate_in(&score, board) {
+                            Some(moves) => println!("Hint: column {} ({})", move_list.last().unwrap() + 1, eval::describe_mate(moves)),
+                            None => println!("Hint: column {}", move_list.last().unwrap() + 1),
+                        }
+                        continue;
+                    },
+                    Ok(PlayerInput::Eval) => {
+                        let (score, _move_list) = eval::search(board, &hint_evaluation, hint_depth);
+                        println!("Static: {:?}", board.last_score());
+                        match eval::mate_in(&score, board) {
+                            Some(moves) => println!("Depth {hint_depth}: {}", eval::describe_mate(moves)),
+                            None => println!("Depth {hint_depth}: {score:?}"),
+                        }
+                        if let Some(value) = tablebase::loaded().and_then(|table| table.probe(board)) {
+                            println!("Tablebase: {value}");
+                        }
+                        continue;
+                    },
+                    Ok(PlayerInput::Pv) => {
+                        let (_score, move_list) = eval::search(board, &hint_evaluation, hint_depth);
+                        let pv: Vec<String> = move_list.iter().rev().map(|column| (column + 1).to_string()).collect();
+                        println!("Principal variation: {}", pv.join(" "));
+                        continue;
+                    },
+                    Ok(PlayerInput::Analyse) => {
+                        print!("{}", analysis::analyse(board, &hint_evaluation, hint_depth));
+                        continue;
+                    },
+                    Ok(PlayerInput::Threats) => {
+                        println!("{}", zugzwang::analyse(board));
+                        continue;
+                    },
+                    Ok(PlayerInput::Moves) => {
+                        let played: Vec<String> = board.move_list().iter().map(|column| (column + 1).to_string()).collect();
+                        let legal: Vec<String> = board.legal_moves().iter().map(|column| (column + 1).to_string()).collect();
+                        println!("Played: {}", played.join(" "));
+                        println!("Legal: {}", legal.join(" "));
+                        continue;
+                    },
+                    Ok(PlayerInput::Variations) => {
+                        let history = board.history();
+                        let next: Vec<String> = history.variations().iter().map(|column| (column + 1).to_string()).collect();
+                        match next.split_first() {
+                            Some((main, [])) => println!("Main line: {main}"),
+                            Some((main, variations)) => println!("Main line: {main}, variations: {}", variations.join(" ")),
+                            None => println!("No moves were played from this position"),
+                        }
+                        continue;
+                    },
+                    Ok(PlayerInput::Variation(column)) => {
+                        let history = board.history();
+                        match history.child(history.cursor(), column - 1) {
+                            Some(child) => {
+                                board.goto(history.line_end(child));
+                                show(board, format);
+                                println!("{:?}", board.last_score());
                             },
-                            Ok(GameState::Draw) => {
-                                println!("Draw!");
-                                break;
+                            None => println!("No variation continues with column {column} here"),
+                        }
+                        continue;
+                    },
+                    Ok(PlayerInput::Promote) => {
+                        board.promote();
+                        println!("The current line is now the main line");
+                        continue;
+                    },
+                    Ok(PlayerInput::Save(path)) => {
+                        match fs::write(&path, save(board)) {
+                            Ok(()) => println!("Saved to {path}"),
+                            Err(error) => println!("Could not save to {path}: {error}"),
+                        }
+                        continue;
+                    },
+                    Ok(PlayerInput::Load(path)) => {
+                        let loaded = fs::read_to_string(&path)
+                            .map_err(|error| error.to_string())
+                            .and_then(|input| load(&input, board.k()));
+                        match loaded {
+                            Ok(game) => {
+                                *board = game;
+                                show(board, format);
                             },
-                            Err(error) => {
-                                match error {
-                                    InsertError::InvalidColumn => println!("Column does not exist."),
-                                    InsertError::ColumnFull => println!("Column is already full."),
-                                }
+                            Err(error) => println!("Could not load {path}: {error}"),
+                        }
+                        continue;
+                    },
+                    Ok(PlayerInput::Swap) => {
+                        players.swap(0, 1);
+                        println!("Red is now {}, Yellow is now {}", players[0], players[1]);
+                        continue;
+                    },
+                    Ok(PlayerInput::Depth(depth)) => {
+                        hint_depth = depth;
+                        for player in &mut players {
+                            if let Player::Computer(player_depth, _) = player {
+                                *player_depth = depth;
                             }
                         }
+                        println!("Search depth set to {depth}");
+                        continue;
                     },
-                    Ok(PlayerInput::Undo) => {
-                        board.undo();
-                        board.undo();
-                        println!("{board}");
-                        println!("{:?}", board.last_score());
+                    Ok(PlayerInput::Show) => {
+                        show(board, format);
+                        continue;
+                    },
+                    Ok(PlayerInput::Help) => {
+                        println!("{COMMANDS}");
+                        continue;
                     },
                     Ok(PlayerInput::Quit) => break,
-                    Err(ParseInputError::OutOfRange) => println!("Column is out of range"),
-                    Err(ParseInputError::Parse(error)) => println!("Could not parse input, try again: {error:?}")
+                    Err(ParseInputError::OutOfRange) => {
+                        println!("Column is out of range");
+                        continue;
+                    },
+                    Err(ParseInputError::Parse(error)) => {
+                        println!("Could not parse input, try again: {error:?}");
+                        continue;
+                    },
+                    Err(ParseInputError::UnknownCommand(command)) => {
+                        println!("Unknown command '{command}', type help for a list of commands");
+                        continue;
+                    },
+                    Err(ParseInputError::BadArgument(command)) => {
+                        println!("'{command}' needs a valid argument, type help for a list of commands");
+                        continue;
+                    },
                 }
             }
-            Player::Computer(depth) => {
-                let (_score, move_list) = board.minimax_rec(*depth);
-                let column = move_list.last().unwrap();
-                let state = board.run(*column);
-                println!("{board}");
-                println!("{:?}", board.last_score());
-                match state {
-                    Ok(GameState::InProgress) => {},
-                    Ok(GameState::Win(winner)) => {
-                        println!("{winner} wins!");
-                        break;
-                    },
-                    Ok(GameState::Draw) => {
-                        println!("Draw!");
-                        break;
-                    },
-                    Err(error) => {
-                        match error {
-                            InsertError::InvalidColumn => println!("Column does not exist."),
-                            InsertError::ColumnFull => println!("Column is already full."),
+            Player::Computer(depth, evaluation) => {
+                let move_list = match ponder.take().and_then(|ponder| ponder.resolve(board)) {
+                    Some((score, move_list, stats)) => {
+                        if options.verbose {
+                            print!("Ponder hit, ");
+                            print_info(&stats, &score, &move_list);
                         }
-                    }
+                        move_list
+                    },
+                    None => eval::search_with_info(board, &evaluation, depth, &Default::default(), |stats, score, pv| {
+                        if options.verbose { print_info(stats, score, pv) }
+                        ControlFlow::Continue(())
+                    }).1,
+                };
+
+                expected = ponder::Expected::from_pv(board, &move_list);
+                *move_list.last().unwrap()
+            },
+            _ => player.engine_move(board).unwrap(),
+        };
+
+        let state = board.run(column);
+        show(board, format);
+        println!("{:?}", board.last_score());
+        match state {
+            Ok(GameState::InProgress) => {},
+            Ok(GameState::Win(winner)) => {
+                println!("{winner} wins!");
+                break;
+            },
+            Ok(GameState::Draw) => {
+                println!("Draw!");
+                break;
+            },
+            Err(error) => {
+                match error {
+                    InsertError::InvalidColumn => println!("Column does not exist."),
+                    InsertError::ColumnFull => println!("Column is already full."),
+                    InsertError::GameOver => println!("The game is already over."),
                 }
             }
         }
     }
 }
 
+const MOVES_PREFIX: &str = "moves:";
+
+// The grid, followed by the moves with their variations
+fn save(board: &Game) -> String {
+    format!("{}\n{MOVES_PREFIX} {}\n", board.serialize().trim_end(), board.history())
+}
+
+// Also reads bare grids, which have no variations
+fn load(input: &str, k: usize) -> Result<Game, String> {
+    let (moves, grid): (Vec<&str>, Vec<&str>) = input.lines().partition(|line| line.starts_with(MOVES_PREFIX));
+    let game = Game::deserialize(&grid.join("\n"), k).map_err(|error| error.to_string())?;
+    let Some(moves) = moves.first() else { return Ok(game) };
+
+    let history = VariationTree::parse(&moves[MOVES_PREFIX.len()..]).map_err(|error| error.to_string())?;
+    Game::from_history(game.width(), game.height(), k, history).map_err(|error| format!("{error:?}"))
+}
+
+fn print_info(stats: &SearchStats, score: &Score, pv: &[usize]) {
+    let pv: Vec<String> = pv.iter().rev().map(|column| (column + 1).to_string()).collect();
+    println!("{stats} score {score:?} pv {}", pv.join(" "));
+}
+
+impl Player {
+    // None for humans, whose moves come from the prompt
+    fn engine_move(&self, board: &mut Game) -> Option<usize> {
+        let (_score, move_list) = self.search(board)?;
+        move_list.last().copied()
+    }
+
+    // Evaluation and principal variation, with the best move last
+    fn search(&self, board: &mut Game) -> Option<(Score, Vec<usize>)> {
+        match self {
+            Player::Human => None,
+            Player::Computer(depth, evaluation) => Some(eval::search(board, evaluation, *depth)),
+            Player::Mcts(options) => {
+                let pv = mcts::search(board, options, &mut rng::Rng::from_time());
+                let score = eval::line_score(board, &pv);
+                Some((score, pv.into_iter().rev().collect()))
+            },
+            Player::Difficulty(difficulty) => {
+                let line = difficulty.choose(board, &mut rng::Rng::from_time())?;
+                let score = eval::line_score(board, &line);
+                Some((score, line.into_iter().rev().collect()))
+            },
+        }
+    }
+}
+
+impl Display for Player {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Player::Human => write!(f, "human"),
+            Player::Computer(depth, evaluation) => {
+                write!(f, "computer:{depth}")?;
+                if !matches!(evaluation, eval::Evaluation::Default) {
+                    write!(f, ":{evaluation}")?;
+                }
+                Ok(())
+            },
+            Player::Mcts(options) => {
+                match options.limit {
+                    mcts::Limit::Iterations(iterations) => write!(f, "mcts:{iterations}")?,
+                    mcts::Limit::Time(time) => write!(f, "mcts:{}ms", time.as_millis())?,
+                }
+                if options.exploration != mcts::MctsOptions::DEFAULT_EXPLORATION {
+                    write!(f, ":c={}", options.exploration)?;
+                }
+                if options.heuristic {
+                    write!(f, ":heuristic")?;
+                }
+                Ok(())
+            },
+            Player::Difficulty(difficulty) => match difficulty.name() {
+                Some(name) => write!(f, "{name}"),
+                None => write!(f, "level:depth={}:t={}:blunder={}", difficulty.depth, difficulty.temperature, difficulty.blunder_rate),
+            },
+        }
+    }
+}
+
 enum PlayerInput {
     Column(usize),
     Undo,
+    Redo,
+    Hint,
+    Eval,
+    Pv,
+    Analyse,
+    Threats,
+    Moves,
+    Variations,
+    Variation(usize),
+    Promote,
+    Save(String),
+    Load(String),
+    Swap,
+    Depth(usize),
+    Show,
+    Help,
     Quit
 }
 
@@ -113,13 +520,19 @@ enum PlayerInput {
 enum ParseInputError {
     Parse(ParseIntError),
     OutOfRange,
+    UnknownCommand(String),
+    BadArgument(&'static str),
 }
 
 fn parse_input(columns: usize) -> Result<PlayerInput, ParseInputError> {
-    println!("Enter a column number: ");
-    let input = input();
-    
-    match input.parse() {
+    println!("Enter a column number or a command (help for a list): ");
+    let Some(input) = input() else { return Ok(PlayerInput::Quit) };
+    let (command, argument) = match input.split_once(char::is_whitespace) {
+        Some((command, argument)) => (command.to_lowercase(), Some(argument.trim().to_string())),
+        None => (input.to_lowercase(), None),
+    };
+
+    match command.parse() {
         Ok(column) => {
             if (1..=columns).contains(&column) {
                 Ok(PlayerInput::Column(column))
@@ -127,16 +540,45 @@ fn parse_input(columns: usize) -> Result<PlayerInput, ParseInputError> {
                 Err(ParseInputError::OutOfRange)
             }
         },
-        Err(error) => match input.as_str() {
+        Err(error) => match command.as_str() {
             "u" | "undo" => Ok(PlayerInput::Undo),
+            "redo" => Ok(PlayerInput::Redo),
+            "hint" => Ok(PlayerInput::Hint),
+            "eval" => Ok(PlayerInput::Eval),
+            "pv" => Ok(PlayerInput::Pv),
+            "analyse" | "analyze" => Ok(PlayerInput::Analyse),
+            "threats" => Ok(PlayerInput::Threats),
+            "moves" => Ok(PlayerInput::Moves),
+            "variations" => Ok(PlayerInput::Variations),
+            "variation" => match argument.map(|column| column.parse()) {
+                Some(Ok(column)) if column > 0 => Ok(PlayerInput::Variation(column)),
+                _ => Err(ParseInputError::BadArgument("variation")),
+            },
+            "promote" => Ok(PlayerInput::Promote),
+            "save" => argument.map(PlayerInput::Save).ok_or(ParseInputError::BadArgument("save")),
+            "load" => argument.map(PlayerInput::Load).ok_or(ParseInputError::BadArgument("load")),
+            "swap" => Ok(PlayerInput::Swap),
+            "depth" => match argument {
+                Some(depth) => match depth.parse() {
+                    Ok(depth) if depth > 0 => Ok(PlayerInput::Depth(depth)),
+                    _ => Err(ParseInputError::BadArgument("depth")),
+                },
+                None => Err(ParseInputError::BadArgument("depth")),
+            },
+            "show" => Ok(PlayerInput::Show),
+            "help" | "?" => Ok(PlayerInput::Help),
             "q" | "quit" => Ok(PlayerInput::Quit),
-            _ => Err(ParseInputError::Parse(error))
+            "" => Err(ParseInputError::Parse(error)),
+            _ => Err(ParseInputError::UnknownCommand(command)),
         }
     }
 }
 
-fn input() -> String {
+// None once stdin is closed
+fn input() -> Option<String> {
     let mut input = String::new();
-    stdin().read_line(&mut input).unwrap();
-    input.trim().to_lowercase()
+    match stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_string()),
+    }
 }
@@ -0,0 +1,221 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    board::{Color, Game, GameState, Score},
+    rng::Rng,
+};
+
+// Share of heuristic rollout moves that are played at random instead, to keep rollouts varied
+const ROLLOUT_RANDOMNESS: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Iterations(u64),
+    Time(Duration),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MctsOptions {
+    pub limit: Limit,
+    pub exploration: f64,
+    // Rollouts prefer the move with the best chain score instead of playing uniformly at random
+    pub heuristic: bool,
+}
+
+impl MctsOptions {
+    pub const DEFAULT_EXPLORATION: f64 = std::f64::consts::SQRT_2;
+}
+
+impl Default for MctsOptions {
+    fn default() -> Self {
+        Self {
+            limit: Limit::Iterations(10000),
+            exploration: Self::DEFAULT_EXPLORATION,
+            heuristic: false,
+        }
+    }
+}
+
+struct Node {
+    column: usize,
+    parent: usize,
+    children: Vec<usize>,
+    untried: Vec<usize>,
+    visits: u32,
+    // Sum of the results for the player who moved into this node, 1 for a win and 0.5 for a draw
+    reward: f64,
+    mover: Color,
+    state: GameState,
+}
+
+impl Node {
+    fn uct(&self, parent_visits: u32, exploration: f64) -> f64 {
+        let visits = self.visits as f64;
+        self.reward / visits + exploration * ((parent_visits as f64).ln() / visits).sqrt()
+    }
+}
+
+// UCT search, returning the principal variation in the order the moves are played
+pub fn search(game: &mut Game, options: &MctsOptions, rng: &mut Rng) -> Vec<usize> {
+    if !matches!(game.game_state(), GameState::InProgress) {
+        return Vec::new();
+    }
+
+    let mut nodes = vec![Node {
+        column: 0,
+        parent: 0,
+        children: Vec::new(),
+        untried: game.legal_moves(),
+        visits: 0,
+        reward: 0.0,
+        mover: game.turn().other(),
+        state: GameState::InProgress,
+    }];
+
+    let start = Instant::now();
+    let mut iterations = 0;
+    loop {
+        match options.limit {
+            Limit::Iterations(limit) if iterations >= limit => break,
+            Limit::Time(limit) if iterations > 0 && start.elapsed() >= limit => break,
+            _ => {},
+        }
+        iterations += 1;
+
+        // Selection
+        let mut node = 0;
+        let mut played = 0;
+        while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+            let parent_visits = nodes[node].visits;
+            node = *nodes[node].children.iter()
+                .max_by(|&&a, &&b| {
+                    let a = nodes[a].uct(parent_visits, options.exploration);
+                    let b = nodes[b].uct(parent_visits, options.exploration);
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+            game.run_unchecked(nodes[node].column);
+            played += 1;
+        }
+
+        // Expansion
+        if !nodes[node].untried.is_empty() {
+            let untried = &mut nodes[node].untried;
+            let column = untried.swap_remove(rng.below(untried.len()));
+            let mover = game.turn();
+            let state = game.run_unchecked(column).unwrap();
+            played += 1;
+
+            let untried = match state {
+                GameState::InProgress => game.legal_moves(),
+                _ => Vec::new(),
+            };
+            nodes.push(Node { column, parent: node, children: Vec::new(), untried, visits: 0, reward: 0.0, mover, state });
+            let child = nodes.len() - 1;
+            nodes[node].children.push(child);
+            node = child;
+        }
+
+        // Simulation
+        let mut state = nodes[node].state;
+        while let GameState::InProgress = state {
+            let column = rollout_move(game, options.heuristic, rng);
+            state = game.run_unchecked(column).unwrap();
+            played += 1;
+        }
+        for _ in 0..played {
+            game.undo_unchecked();
+        }
+
+        // Backpropagation
+        loop {
+            let current = &mut nodes[node];
+            current.visits += 1;
+            current.reward += match state {
+                GameState::Win(winner) if winner == current.mover => 1.0,
+                GameState::Win(_) => 0.0,
+                _ => 0.5,
+            };
+            if node == 0 { break }
+            node = current.parent;
+        }
+    }
+
+    // The most visited line
+    let mut pv = Vec::new();
+    let mut node = 0;
+    while let Some(&child) = nodes[node].children.iter().max_by_key(|&&child| nodes[child].visits) {
+        pv.push(nodes[child].column);
+        node = child;
+    }
+    pv
+}
+
+fn rollout_move(game: &mut Game, heuristic: bool, rng: &mut Rng) -> usize {
+    let moves = game.legal_moves();
+    if !heuristic || rng.next_f64() < ROLLOUT_RANDOMNESS {
+        return *rng.choose(&moves).unwrap();
+    }
+
+    // Winning moves have the best chain score, so they are always found
+    let turn = game.turn();
+    let mut best: Option<(Score, usize)> = None;
+    for column in moves {
+        game.run_unchecked(column);
+        let score = game.last_score().clone();
+        game.undo_unchecked();
+
+        let better = best.as_ref().is_none_or(|(best, _)| match turn {
+            Color::Red => score > *best,
+            Color::Yellow => score < *best,
+        });
+        if better { best = Some((score, column)) }
+    }
+    best.unwrap().1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(moves: &[usize]) -> Game {
+        let mut game = Game::new(7, 6, 4);
+        for &column in moves {
+            game.run(column).unwrap();
+        }
+        game
+    }
+
+    fn best_move(game: &mut Game, heuristic: bool, seed: u64) -> usize {
+        let options = MctsOptions { limit: Limit::Iterations(2000), heuristic, ..Default::default() };
+        search(game, &options, &mut Rng::new(seed))[0]
+    }
+
+    #[test]
+    fn takes_an_immediate_win() {
+        for heuristic in [false, true] {
+            for seed in 0..3 {
+                // Red completes the first column, then Yellow the second one
+                assert_eq!(best_move(&mut game(&[0, 1, 0, 1, 0, 1]), heuristic, seed), 0);
+                assert_eq!(best_move(&mut game(&[0, 1, 0, 1, 6, 1, 6]), heuristic, seed), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_the_only_threat() {
+        for heuristic in [false, true] {
+            for seed in 0..3 {
+                // Yellow has to cover Red's column, and Red Yellow's row
+                assert_eq!(best_move(&mut game(&[0, 1, 0, 1, 0]), heuristic, seed), 0);
+                assert_eq!(best_move(&mut game(&[6, 1, 6, 2, 0, 3]), heuristic, seed), 4);
+            }
+        }
+    }
+
+    #[test]
+    fn finished_games_have_no_moves() {
+        let mut won = game(&[0, 1, 0, 1, 0, 1, 0]);
+        assert!(search(&mut won, &MctsOptions::default(), &mut Rng::new(1)).is_empty());
+    }
+}
@@ -0,0 +1,550 @@
+use std::{
+    collections::HashMap,
+    io::{self, stdin, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{channel, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    ansi::Ansi,
+    board::{Color, Game, GameState},
+    rng::Rng,
+    Player,
+};
+
+// Protocol, one message per line with 1-based columns:
+//
+//   client -> host: join [red|yellow|spectator] [token], move <column>, quit
+//   host -> client: hello <m> <n> <k>, seat <red|yellow|spectator> [token], moves <column>...,
+//                   move <column>, turn <red|yellow>, waiting <red|yellow>, result <red|yellow|draw>,
+//                   error <message>
+//
+// A player who loses the connection keeps the seat and can reclaim it by joining with the token.
+
+const RECONNECT_ATTEMPTS: usize = 30;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub enum Seat {
+    // A human at the host's terminal
+    Local,
+    Remote,
+    Engine(Player),
+}
+
+pub struct ServeOptions {
+    pub width: usize,
+    pub height: usize,
+    pub k: usize,
+    pub port: u16,
+    pub red: Seat,
+    pub yellow: Seat,
+}
+
+pub struct ConnectOptions {
+    pub address: String,
+    pub seat: Option<Color>,
+    pub spectate: bool,
+    pub player: Player,
+    pub token: Option<String>,
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Red => "red",
+        Color::Yellow => "yellow",
+    }
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    match name {
+        "red" => Some(Color::Red),
+        "yellow" => Some(Color::Yellow),
+        _ => None,
+    }
+}
+
+fn moves_message(game: &Game) -> String {
+    let moves: Vec<String> = game.move_list().iter().map(|column| (column + 1).to_string()).collect();
+    format!("moves {}", moves.join(" ")).trim_end().to_string()
+}
+
+enum Event {
+    Connected(usize, TcpStream),
+    Line(usize, String),
+    Closed(usize),
+    Local(String),
+}
+
+struct Remote {
+    token: String,
+    connection: Option<usize>,
+}
+
+struct Host {
+    game: Game,
+    seats: [Seat; 2],
+    remotes: [Option<Remote>; 2],
+    connections: HashMap<usize, TcpStream>,
+    rng: Rng,
+}
+
+impl Host {
+    fn send(&mut self, id: usize, message: &str) {
+        if let Some(stream) = self.connections.get_mut(&id) {
+            let _ = writeln!(stream, "{message}");
+        }
+    }
+
+    fn broadcast(&mut self, message: &str) {
+        for stream in self.connections.values_mut() {
+            let _ = writeln!(stream, "{message}");
+        }
+    }
+
+    fn index(color: Color) -> usize {
+        match color {
+            Color::Red => 0,
+            Color::Yellow => 1,
+        }
+    }
+
+    fn seat_of(&self, id: usize) -> Option<Color> {
+        [Color::Red, Color::Yellow].into_iter().find(|&color| {
+            self.remotes[Self::index(color)].as_ref().is_some_and(|remote| remote.connection == Some(id))
+        })
+    }
+
+    fn join(&mut self, id: usize, arguments: &[&str]) {
+        let requested = arguments.first().copied();
+        let token = arguments.get(1).copied();
+
+        let seat = match requested {
+            Some("spectator") => None,
+            Some(name) => match parse_color(name) {
+                Some(color) => Some(color),
+                None => return self.send(id, "error unknown seat"),
+            },
+            // Any unclaimed remote seat, or spectating if there is none
+            None => [Color::Red, Color::Yellow].into_iter().find(|&color| {
+                matches!(self.seats[Self::index(color)], Seat::Remote) && self.remotes[Self::index(color)].is_none()
+            }),
+        };
+
+        let (width, height, k) = (self.game.width(), self.game.height(), self.game.k());
+        self.send(id, &format!("hello {width} {height} {k}"));
+
+        match seat {
+            None => self.send(id, "seat spectator"),
+            Some(color) => {
+                let index = Self::index(color);
+                if !matches!(self.seats[index], Seat::Remote) {
+                    return self.send(id, &format!("error {} is not a remote seat", color_name(color)));
+                }
+                match &mut self.remotes[index] {
+                    Some(remote) if remote.connection.is_some() => {
+                        return self.send(id, &format!("error {} is taken", color_name(color)));
+                    },
+                    Some(remote) if token != Some(remote.token.as_str()) => {
+                        return self.send(id, &format!("error {} is reserved, join with its token", color_name(color)));
+                    },
+                    Some(remote) => remote.connection = Some(id),
+                    None => {
+                        let token = format!("{:016x}", self.rng.next_u64());
+                        self.remotes[index] = Some(Remote { token, connection: Some(id) });
+                    },
+                }
+                let token = self.remotes[index].as_ref().unwrap().token.clone();
+                self.send(id, &format!("seat {} {token}", color_name(color)));
+                println!("{} joined as {}", id, color_name(color));
+            },
+        }
+
+        let moves = moves_message(&self.game);
+        self.send(id, &moves);
+        self.announce_turn(Some(id));
+    }
+
+    fn announce_turn(&mut self, only: Option<usize>) {
+        let message = match self.game.game_state() {
+            GameState::InProgress => format!("turn {}", color_name(self.game.turn())),
+            GameState::Win(winner) => format!("result {}", color_name(winner)),
+            GameState::Draw => "result draw".to_string(),
+        };
+        match only {
+            Some(id) => self.send(id, &message),
+            None => self.broadcast(&message),
+        }
+    }
+
+    // Validates and plays a move, telling everyone about it
+    fn play(&mut self, column: usize) -> bool {
+        if !matches!(self.game.game_state(), GameState::InProgress) || self.game.run(column).is_err() {
+            return false;
+        }
+        self.broadcast(&format!("move {}", column + 1));
+        println!("{}", Ansi::auto(&self.game));
+        self.announce_turn(None);
+        true
+    }
+}
+
+pub fn serve(options: &ServeOptions) -> io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", options.port))?;
+    println!("Listening on port {}", listener.local_addr()?.port());
+    host(listener, options)
+}
+
+// Plays one game with the clients of the listener, whatever its port
+fn host(listener: TcpListener, options: &ServeOptions) -> io::Result<()> {
+    let (sender, events) = channel();
+
+    let accepting = sender.clone();
+    thread::spawn(move || {
+        for (id, stream) in listener.incoming().flatten().enumerate() {
+            let Ok(writer) = stream.try_clone() else { continue };
+            if accepting.send(Event::Connected(id, writer)).is_err() { break }
+            let lines = accepting.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(stream).lines() {
+                    let Ok(line) = line else { break };
+                    if lines.send(Event::Line(id, line)).is_err() { return }
+                }
+                let _ = lines.send(Event::Closed(id));
+            });
+        }
+    });
+
+    if [&options.red, &options.yellow].iter().any(|seat| matches!(seat, Seat::Local)) {
+        spawn_stdin(sender);
+    }
+
+    let mut host = Host {
+        game: Game::new(options.width, options.height, options.k),
+        seats: [options.red.clone(), options.yellow.clone()],
+        remotes: [None, None],
+        connections: HashMap::new(),
+        rng: Rng::from_time(),
+    };
+    println!("{}", Ansi::auto(&host.game));
+
+    loop {
+        let turn = host.game.turn();
+        match host.game.game_state() {
+            GameState::InProgress => {},
+            GameState::Win(winner) => {
+                println!("{winner} wins!");
+                break;
+            },
+            GameState::Draw => {
+                println!("Draw!");
+                break;
+            },
+        }
+
+        if let Seat::Engine(player) = &host.seats[Host::index(turn)] {
+            if let Some(column) = player.engine_move(&mut host.game) {
+                host.play(column);
+            }
+            continue;
+        }
+
+        let Ok(event) = events.recv() else { break };
+        match event {
+            Event::Connected(id, stream) => {
+                host.connections.insert(id, stream);
+            },
+            Event::Closed(id) => {
+                host.connections.remove(&id);
+                if let Some(color) = host.seat_of(id) {
+                    host.remotes[Host::index(color)].as_mut().unwrap().connection = None;
+                    println!("{} lost the connection, waiting for a reconnect", color_name(color));
+                    host.broadcast(&format!("waiting {}", color_name(color)));
+                }
+            },
+            Event::Line(id, line) => {
+                let mut words = line.split_whitespace();
+                let command = words.next().unwrap_or_default();
+                let arguments: Vec<&str> = words.collect();
+                match command {
+                    "join" if host.seat_of(id).is_some() => host.send(id, "error already seated"),
+                    "join" => host.join(id, &arguments),
+                    "move" => {
+                        let column = arguments.first().and_then(|column| column.parse::<usize>().ok());
+                        if host.seat_of(id) != Some(turn) {
+                            host.send(id, "error not your turn");
+                        } else if !column.is_some_and(|column| column > 0 && host.play(column - 1)) {
+                            host.send(id, "error illegal move");
+                        }
+                    },
+                    "quit" => {
+                        host.connections.remove(&id);
+                    },
+                    _ => host.send(id, "error unknown command"),
+                }
+            },
+            Event::Local(line) => {
+                if line == "q" || line == "quit" { break }
+                if !matches!(host.seats[Host::index(turn)], Seat::Local) {
+                    println!("It is not your turn");
+                    continue;
+                }
+                match line.parse::<usize>() {
+                    Ok(column) if column > 0 && host.play(column - 1) => {},
+                    _ => println!("Illegal move, enter a column number"),
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn spawn_stdin(sender: Sender<Event>) {
+    thread::spawn(move || {
+        for line in stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(Event::Local(line.trim().to_lowercase())).is_err() { break }
+        }
+    });
+}
+
+fn join_message(options: &ConnectOptions, seat: Option<Color>, token: Option<&str>) -> String {
+    match (options.spectate, seat, token) {
+        (true, _, _) => "join spectator".to_string(),
+        (false, Some(color), Some(token)) => format!("join {} {token}", color_name(color)),
+        (false, Some(color), None) => format!("join {}", color_name(color)),
+        (false, None, _) => "join".to_string(),
+    }
+}
+
+pub fn connect(options: &ConnectOptions) -> io::Result<()> {
+    let mut seat = options.seat;
+    let mut token = options.token.clone();
+    let mut game = Game::new(1, 1, 1);
+    let mut attempts = 0;
+
+    loop {
+        let stream = match TcpStream::connect(&options.address) {
+            Ok(stream) => stream,
+            Err(error) if attempts > 0 && attempts < RECONNECT_ATTEMPTS => {
+                attempts += 1;
+                println!("Reconnecting ({error})...");
+                thread::sleep(RECONNECT_DELAY);
+                continue;
+            },
+            Err(error) => return Err(error),
+        };
+
+        let mut writer = stream.try_clone()?;
+        writeln!(writer, "{}", join_message(options, seat, token.as_deref()))?;
+
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or_default();
+            let arguments: Vec<&str> = words.collect();
+
+            match (command, arguments.as_slice()) {
+                ("hello", [m, n, k]) => {
+                    let parse = |value: &str| value.parse::<usize>().map_err(io::Error::other);
+                    game = Game::new(parse(m)?, parse(n)?, parse(k)?);
+                },
+                ("seat", ["spectator"]) => println!("Watching as a spectator"),
+                ("seat", [color, seat_token]) => {
+                    seat = parse_color(color);
+                    token = Some(seat_token.to_string());
+                    println!("Playing {color}, reconnect with --token {seat_token}");
+                },
+                ("moves", moves) => {
+                    game = Game::new(game.width(), game.height(), game.k());
+                    for column in moves.iter().filter_map(|column| column.parse::<usize>().ok()?.checked_sub(1)) {
+                        let _ = game.run(column);
+                    }
+                    println!("{}", Ansi::auto(&game));
+                },
+                ("move", [column]) => {
+                    if let Some(column) = column.parse::<usize>().ok().and_then(|column| column.checked_sub(1)) {
+                        let _ = game.run(column);
+                        println!("{}", Ansi::auto(&game));
+                    }
+                },
+                ("turn", [color]) => {
+                    println!("{color}'s turn");
+                    if options.spectate || seat != parse_color(color) { continue }
+                    let column = match &options.player {
+                        Player::Human => read_column(&game),
+                        player => player.engine_move(&mut game),
+                    };
+                    match column {
+                        Some(column) => writeln!(writer, "move {}", column + 1)?,
+                        None => {
+                            writeln!(writer, "quit")?;
+                            return Ok(());
+                        },
+                    }
+                },
+                ("waiting", [color]) => println!("Waiting for {color} to reconnect"),
+                ("result", [result]) => {
+                    match parse_color(result) {
+                        Some(_) => println!("{result} wins!"),
+                        None => println!("Draw!"),
+                    }
+                    return Ok(());
+                },
+                ("error", message) => println!("Host: {}", message.join(" ")),
+                _ => {},
+            }
+        }
+
+        // The connection dropped before the game ended
+        if options.spectate || token.is_some() {
+            attempts = 1;
+            println!("Connection lost");
+        } else {
+            return Err(io::Error::other("connection lost"));
+        }
+    }
+}
+
+// None if the user quits
+fn read_column(game: &Game) -> Option<usize> {
+    loop {
+        println!("Enter a column number (q to quit): ");
+        let mut input = String::new();
+        if stdin().read_line(&mut input).ok()? == 0 { return None }
+        match input.trim() {
+            "q" | "quit" => return None,
+            input => match input.parse::<usize>() {
+                Ok(column) if (1..=game.width()).contains(&column) => return Some(column - 1),
+                _ => println!("Column is out of range"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(port: u16) -> Self {
+            let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            Self { writer: stream.try_clone().unwrap(), reader: BufReader::new(stream) }
+        }
+
+        fn send(&mut self, message: &str) {
+            writeln!(self.writer, "{message}").unwrap();
+        }
+
+        fn receive(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        }
+
+        // Joins and reads the replies up to the turn, returning the seat's token
+        fn join(&mut self, arguments: &str) -> Option<String> {
+            self.send(format!("join {arguments}").trim_end());
+            assert_eq!(self.receive(), "hello 4 4 2");
+            let seat = self.receive();
+            self.receive();
+            assert!(self.receive().starts_with("turn "));
+            seat.split_whitespace().nth(2).map(str::to_string)
+        }
+    }
+
+    // A 4x4 board with two in a row, where Red wins by playing column 1 twice
+    fn start() -> u16 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = ServeOptions { width: 4, height: 4, k: 2, port, red: Seat::Remote, yellow: Seat::Remote };
+        thread::spawn(move || host(listener, &options));
+        port
+    }
+
+    #[test]
+    fn host_validates_moves() {
+        let port = start();
+        let mut red = Client::connect(port);
+        let mut yellow = Client::connect(port);
+        red.join("red");
+        yellow.join("yellow");
+
+        yellow.send("move 1");
+        assert_eq!(yellow.receive(), "error not your turn");
+        for column in ["0", "5", "x"] {
+            red.send(&format!("move {column}"));
+            assert_eq!(red.receive(), "error illegal move");
+        }
+
+        red.send("move 1");
+        for client in [&mut red, &mut yellow] {
+            assert_eq!(client.receive(), "move 1");
+            assert_eq!(client.receive(), "turn yellow");
+        }
+    }
+
+    #[test]
+    fn seat_is_reclaimed_with_token() {
+        let port = start();
+        let mut red = Client::connect(port);
+        let mut yellow = Client::connect(port);
+        let token = red.join("red").unwrap();
+        yellow.join("yellow");
+
+        red.send("move 1");
+        assert_eq!(yellow.receive(), "move 1");
+        assert_eq!(yellow.receive(), "turn yellow");
+        drop(red);
+        assert_eq!(yellow.receive(), "waiting red");
+
+        let mut red = Client::connect(port);
+        red.send("join red");
+        assert_eq!(red.receive(), "hello 4 4 2");
+        assert_eq!(red.receive(), "error red is reserved, join with its token");
+        red.send("join red 0123456789abcdef");
+        assert_eq!(red.receive(), "hello 4 4 2");
+        assert_eq!(red.receive(), "error red is reserved, join with its token");
+
+        red.send(&format!("join red {token}"));
+        assert_eq!(red.receive(), "hello 4 4 2");
+        assert_eq!(red.receive(), format!("seat red {token}"));
+        assert_eq!(red.receive(), "moves 1");
+        assert_eq!(red.receive(), "turn yellow");
+    }
+
+    #[test]
+    fn spectators_receive_every_move() {
+        let port = start();
+        let mut spectator = Client::connect(port);
+        let mut red = Client::connect(port);
+        let mut yellow = Client::connect(port);
+        spectator.join("spectator");
+        red.join("red");
+        yellow.join("yellow");
+
+        red.send("move 1");
+        for client in [&mut red, &mut yellow] {
+            assert_eq!(client.receive(), "move 1");
+            assert_eq!(client.receive(), "turn yellow");
+        }
+        yellow.send("move 2");
+        for client in [&mut red, &mut yellow] {
+            assert_eq!(client.receive(), "move 2");
+            assert_eq!(client.receive(), "turn red");
+        }
+        red.send("move 1");
+
+        let lines: Vec<String> = (0..6).map(|_| spectator.receive()).collect();
+        assert_eq!(lines, ["move 1", "turn yellow", "move 2", "turn red", "move 1", "result red"]);
+    }
+}
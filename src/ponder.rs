@@ -0,0 +1,157 @@
+use std::{
+    ops::ControlFlow,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    board::{Game, GameState, Score, SearchLimits, SearchStats},
+    eval::{self, Evaluation},
+};
+
+type SearchResult = (Score, Vec<usize>, SearchStats);
+
+// The reply a search expects to the computer's move, from its principal variation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expected {
+    // The position after the computer's move
+    moves: Vec<usize>,
+    reply: usize,
+}
+
+impl Expected {
+    // `pv` is the result of a search of `game`, with the best move last. None if it does not
+    // go as far as a reply.
+    pub fn from_pv(game: &Game, pv: &[usize]) -> Option<Self> {
+        let (&column, rest) = pv.split_last()?;
+        let &reply = rest.last()?;
+        let mut moves = game.move_list().to_vec();
+        moves.push(column);
+        Some(Self { moves, reply })
+    }
+
+    // The game is at the position the reply is expected in
+    pub fn applies(&self, game: &Game) -> bool {
+        self.moves == game.move_list()
+    }
+
+    pub fn is_reply(&self, column: usize) -> bool {
+        column == self.reply
+    }
+}
+
+// A search of the position after the expected reply, running while the opponent thinks. It has to
+// be finished or cancelled before searching, so the two searches don't compete for the processor.
+pub struct Ponder {
+    // The position being searched
+    moves: Vec<usize>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<SearchResult>,
+}
+
+impl Ponder {
+    // None if the game is elsewhere, or if the expected reply is illegal or ends the game
+    pub fn start(game: &Game, expected: &Expected, evaluation: &Evaluation, depth: usize) -> Option<Self> {
+        if !expected.applies(game) {
+            return None;
+        }
+        let mut game = game.clone();
+        if !matches!(game.run(expected.reply), Ok(GameState::InProgress)) {
+            return None;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let limits = SearchLimits { stop: Some(stop.clone()), ..Default::default() };
+        let moves = game.move_list().to_vec();
+        let evaluation = evaluation.clone();
+        let handle = thread::spawn(move || {
+            eval::search_with_info(&mut game, &evaluation, depth, &limits, |_, _, _| ControlFlow::Continue(()))
+        });
+
+        Some(Self { moves, stop, handle })
+    }
+
+    pub fn is_hit(&self, game: &Game) -> bool {
+        self.moves == game.move_list()
+    }
+
+    // The search result if the game reached the searched position, cancelling the search otherwise
+    pub fn resolve(self, game: &Game) -> Option<SearchResult> {
+        if self.is_hit(game) {
+            return Some(self.finish());
+        }
+        self.cancel();
+        None
+    }
+
+    // Waits for the search to complete its depth
+    pub fn finish(self) -> SearchResult {
+        self.handle.join().expect("the ponder thread does not panic")
+    }
+
+    pub fn cancel(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // After 4 4, with the computer answering 3 and expecting 3 in reply
+    fn expected() -> (Game, Expected) {
+        let mut game = Game::new(7, 6, 4);
+        game.run(3).unwrap();
+        game.run(3).unwrap();
+        let expected = Expected::from_pv(&game, &[5, 2, 2]).unwrap();
+        game.run(2).unwrap();
+        (game, expected)
+    }
+
+    #[test]
+    fn reply_comes_from_the_principal_variation() {
+        let (game, expected) = expected();
+        assert!(expected.applies(&game));
+        assert!(expected.is_reply(2));
+        assert!(!expected.is_reply(5));
+        assert_eq!(Expected::from_pv(&game, &[2]), None);
+    }
+
+    #[test]
+    fn ponder_needs_the_expected_position() {
+        let (mut game, expected) = expected();
+        game.undo();
+        assert!(Ponder::start(&game, &expected, &Evaluation::Default, 4).is_none());
+    }
+
+    #[test]
+    fn hit_gives_the_search_of_the_position() {
+        let (mut game, expected) = expected();
+        let ponder = Ponder::start(&game, &expected, &Evaluation::Default, 4).unwrap();
+        game.run(2).unwrap();
+
+        let (score, _, stats) = ponder.resolve(&game).unwrap();
+        let (direct, _, direct_stats) = eval::search_with_info(&mut game, &Evaluation::Default, 4, &Default::default(), |_, _, _| ControlFlow::Continue(()));
+        assert_eq!((score, stats.depth), (direct, direct_stats.depth));
+    }
+
+    #[test]
+    fn miss_cancels_the_search() {
+        let (mut game, expected) = expected();
+        // Deep enough to run until it is cancelled
+        let ponder = Ponder::start(&game, &expected, &Evaluation::Default, 42).unwrap();
+        game.run(0).unwrap();
+
+        assert!(ponder.resolve(&game).is_none());
+        eval::search(&mut game, &Evaluation::Default, 2);
+    }
+
+    #[test]
+    fn cancel_stops_the_search() {
+        let (game, expected) = expected();
+        let ponder = Ponder::start(&game, &expected, &Evaluation::Default, 42).unwrap();
+        ponder.cancel();
+        eval::search(&mut game.clone(), &Evaluation::Default, 2);
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    io::{stdin, stdout, BufRead, Write},
+    ops::ControlFlow,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{board::{Color, Game, GameState, Score, SearchLimits}, eval::{self, Evaluation}};
+
+const NAME: &str = "connect-mnk";
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Limits {
+    depth: Option<usize>,
+    time: Option<Duration>,
+    nodes: Option<u64>,
+    // Hold bestmove back until stop, even once the search has ended
+    infinite: bool,
+}
+
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    infinite: bool,
+}
+
+impl Search {
+    // An infinite search only ends with stop, which any other command implies
+    fn wait(self) {
+        if self.infinite {
+            self.stop();
+        } else {
+            let _ = self.handle.join();
+        }
+    }
+
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        let _ = self.handle.join();
+    }
+}
+
+// Line-based protocol for GUIs and test harnesses, see `help` for the commands
+pub fn run() {
+    let mut game = Game::new(7, 6, 4);
+    let mut evaluation = Evaluation::Default;
+    let mut search: Option<Search> = None;
+
+    for line in stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else { continue };
+        let arguments: Vec<&str> = words.collect();
+
+        // Only these commands may be sent while a search is running
+        match command {
+            "stop" => {
+                if let Some(search) = search.take() { search.stop() }
+                continue;
+            },
+            "isready" => {
+                respond("readyok");
+                continue;
+            },
+            "quit" => break,
+            _ => if let Some(search) = search.take() { search.wait() },
+        }
+
+        match command {
+            "protocol" => {
+                respond(&format!("id name {NAME} {}", env!("CARGO_PKG_VERSION")));
+                respond("protocolok");
+            },
+            "help" => respond(HELP),
+            "new" => match parse_size(&arguments) {
+                Some((m, n, k)) => {
+                    game = Game::new(m, n, k);
+                    respond("ok");
+                },
+                None => respond("error usage: new <m> <n> <k> with m and n at least k"),
+            },
+            "rules" => match arguments.as_slice() {
+                ["gravity"] => respond("ok"),
+                _ => respond("error supported rules: gravity"),
+            },
+            "evaluator" => match arguments.as_slice() {
+                ["weights", path] => match Evaluation::load(path.into()) {
+                    Ok(selected) => {
+                        evaluation = selected;
+                        respond("ok");
+                    },
+                    Err(error) => respond(&format!("error could not read {path}: {error}")),
+                },
+                [name] if Evaluation::parse(name).is_some() => {
+                    evaluation = Evaluation::parse(name).unwrap_or_default();
+                    respond("ok");
+                },
+                _ => respond(&format!("error supported evaluators: {}, or weights <file>", Evaluation::NAMES)),
+            },
+            "position" => match set_position(&game, &arguments) {
+                Ok(position) => {
+                    game = position;
+                    respond("ok");
+                },
+                Err(error) => respond(&format!("error {error}")),
+            },
+            "show" => {
+                print!("{game}");
+                respond(&format!("turn {}", game.turn()));
+            },
+            "go" => match parse_limits(&arguments) {
+                Ok(limits) => search = Some(start(game.clone(), evaluation.clone(), limits)),
+                Err(error) => respond(&format!("error {error}")),
+            },
+            _ => respond(&format!("error unknown command {command}")),
+        }
+    }
+
+    if let Some(search) = search.take() { search.stop() }
+}
+
+const HELP: &str = "\
+protocol                    identify the engine
+isready                     answered with readyok
+new <m> <n> <k>             start an empty m x n board with k in a row to win
+rules gravity               select the rules variant
+evaluator <name>            search with default, chain, weighted or zugzwang
+evaluator weights <file>    search with the weights written by tune
+position [moves <c>...]     set the position from the empty board and 1-based columns
+go [depth <d>] [movetime <ms>] [nodes <n>] [infinite]
+                            search, streaming info lines and ending with bestmove,
+                            which infinite holds back until stop
+stop                        stop the search and report the best move so far
+show                        print the board
+quit                        exit";
+
+fn respond(message: &str) {
+    let mut out = stdout().lock();
+    let _ = writeln!(out, "{message}");
+    let _ = out.flush();
+}
+
+fn parse_size(arguments: &[&str]) -> Option<(usize, usize, usize)> {
+    let [m, n, k] = arguments else { return None };
+    let (m, n, k) = (m.parse().ok()?, n.parse().ok()?, k.parse().ok()?);
+    (k > 0 && m >= k && n >= k).then_some((m, n, k))
+}
+
+fn set_position(game: &Game, arguments: &[&str]) -> Result<Game, String> {
+    let mut position = Game::new(game.width(), game.height(), game.k());
+    let moves = match arguments {
+        [] => &[][..],
+        ["moves", moves @ ..] => moves,
+        _ => return Err("usage: position [moves <c>...]".to_string()),
+    };
+
+    for (i, column) in moves.iter().enumerate() {
+        if !matches!(position.game_state(), GameState::InProgress) {
+            return Err(format!("move {} is played after the game has ended", i + 1));
+        }
+        let column = match column.parse::<usize>() {
+            Ok(column) if column > 0 => column - 1,
+            _ => return Err(format!("bad move {column}")),
+        };
+        if position.run(column).is_err() {
+            return Err(format!("illegal move {}", column + 1));
+        }
+    }
+
+    Ok(position)
+}
+
+fn parse_limits(arguments: &[&str]) -> Result<Limits, String> {
+    let mut limits = Limits::default();
+    let mut arguments = arguments.iter();
+
+    while let Some(&limit) = arguments.next() {
+        if limit == "infinite" {
+            limits.infinite = true;
+            continue;
+        }
+        let value = arguments.next().ok_or(format!("missing value for {limit}"))?;
+        let value: u64 = value.parse().map_err(|_| format!("bad value {value} for {limit}"))?;
+        match limit {
+            "depth" if value > 0 => limits.depth = Some(value as usize),
+            "movetime" => limits.time = Some(Duration::from_millis(value)),
+            "nodes" => limits.nodes = Some(value),
+            _ => return Err(format!("bad limit {limit} {value}")),
+        }
+    }
+
+    Ok(limits)
+}
+
+// Relative to the side to move, with decided positions reported as win or loss in a number of
+// the winner's moves
+fn format_score(score: &Score, game: &Game) -> String {
+    let sign = match game.turn() {
+        Color::Red => 1,
+        Color::Yellow => -1,
+    };
+    match eval::mate_in(score, game) {
+        Some(moves) if moves > 0 => format!("win {moves}"),
+        Some(moves) => format!("loss {}", -moves),
+        None => {
+            let values: Vec<String> = score.values().iter().map(|value| (value * sign).to_string()).collect();
+            format!("vector {}", values.join(","))
+        },
+    }
+}
+
+// Iterative deepening in a background thread, which the limits and stop end right away with the
+// best move of the last completed depth
+fn start(mut game: Game, evaluation: Evaluation, limits: Limits) -> Search {
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+
+    let handle = thread::spawn(move || {
+        let root = game.clone();
+        let empty = game.width() * game.height() - game.move_list().len();
+        let max_depth = limits.depth.unwrap_or(empty).min(empty);
+        let best_move = if matches!(game.game_state(), GameState::InProgress) {
+            let search_limits = SearchLimits { stop: Some(flag.clone()), nodes: limits.nodes, time: limits.time };
+            let (_score, pv, _stats) = eval::search_with_info(&mut game, &evaluation, max_depth, &search_limits, |stats, score, pv| {
+                let line: Vec<String> = pv.iter().rev().map(|column| (column + 1).to_string()).collect();
+                respond(&format!("info {stats} score {} pv {}", format_score(score, &root), line.join(" ")));
+
+                if eval::decided(score).is_some() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+            });
+            pv.last().copied()
+        } else {
+            None
+        };
+        while limits.infinite && !flag.load(Ordering::Relaxed) {
+            thread::park();
+        }
+
+        match best_move {
+            Some(column) => respond(&format!("bestmove {}", column + 1)),
+            None => respond("bestmove none"),
+        }
+    });
+
+    Search { stop, handle, infinite: limits.infinite }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(moves: &[&str]) -> Game {
+        set_position(&Game::new(7, 6, 4), &[&["moves"], moves].concat()).unwrap()
+    }
+
+    #[test]
+    fn set_position_plays_the_moves() {
+        assert_eq!(game(&["4", "4", "3"]).move_list(), [3, 3, 2]);
+        let reset = set_position(&game(&["4"]), &[]).unwrap();
+        assert_eq!((reset.width(), reset.height(), reset.k(), reset.move_list().len()), (7, 6, 4, 0));
+
+        let position = Game::new(7, 6, 4);
+        assert!(set_position(&position, &["4"]).is_err());
+        assert!(set_position(&position, &["moves", "0"]).is_err());
+        assert!(set_position(&position, &["moves", "8"]).is_err());
+        assert!(set_position(&position, &["moves", "1", "1", "1", "1", "1", "1", "1"]).is_err());
+        let won = ["1", "2", "1", "2", "1", "2", "1"];
+        assert!(set_position(&position, &[&["moves"], &won[..]].concat()).is_ok());
+        assert!(set_position(&position, &[&["moves"], &won[..], &["3"]].concat()).is_err());
+    }
+
+    #[test]
+    fn parse_limits_reads_every_limit() {
+        let limits = parse_limits(&["depth", "6", "movetime", "250", "nodes", "1000"]).unwrap();
+        assert_eq!(limits.depth, Some(6));
+        assert_eq!(limits.time, Some(Duration::from_millis(250)));
+        assert_eq!(limits.nodes, Some(1000));
+        assert!(!limits.infinite);
+
+        let limits = parse_limits(&["infinite"]).unwrap();
+        assert!(limits.infinite && limits.depth.is_none() && limits.time.is_none());
+        assert!(parse_limits(&["depth", "6", "infinite"]).unwrap().infinite);
+
+        for arguments in [&["depth"][..], &["depth", "0"], &["depth", "-1"], &["nodes", "x"], &["ponder", "1"]] {
+            assert!(parse_limits(arguments).is_err(), "{arguments:?}");
+        }
+    }
+
+    #[test]
+    fn format_score_is_relative_to_the_side_to_move() {
+        // Red to move completes a column
+        let mut win = game(&["1", "2", "1", "2", "1", "2"]);
+        let (score, _) = eval::search(&mut win, &Evaluation::Default, 2);
+        assert_eq!(format_score(&score, &win), "win 1");
+
+        // Yellow to move cannot block both ends of Red's row
+        let mut loss = game(&["2", "2", "3", "3", "4"]);
+        let (score, _) = eval::search(&mut loss, &Evaluation::Default, 3);
+        assert_eq!(format_score(&score, &loss), "loss 1");
+
+        let score = Score::from(vec![0, 3, -1]);
+        assert_eq!(format_score(&score, &game(&[])), "vector 0,3,-1");
+        assert_eq!(format_score(&score, &game(&["4"])), "vector 0,-3,1");
+    }
+}
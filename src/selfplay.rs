@@ -0,0 +1,142 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::PathBuf};
+
+use crate::{board::{Color, Game, GameState, Score}, rng::Rng, tournament, Player};
+
+pub struct SelfPlayOptions {
+    pub width: usize,
+    pub height: usize,
+    pub k: usize,
+    pub red: Player,
+    pub yellow: Player,
+    pub games: usize,
+    pub opening_moves: usize,
+    pub seed: u64,
+    pub output: PathBuf,
+}
+
+struct Record {
+    moves: Vec<usize>,
+    position: String,
+    turn: Color,
+    score: Score,
+    eval: Score,
+    column: usize,
+}
+
+// Rows from top to bottom separated by slashes, e.g. `_______/.../__XO___`
+fn position(game: &Game) -> String {
+    game.serialize()
+        .lines()
+        .map(|row| row.split_whitespace().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn values(score: &Score) -> String {
+    let values: Vec<String> = score.values().iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+// One JSON object per line, the result is from Red's point of view
+fn write_record(out: &mut impl Write, record: &Record, result: f64) -> io::Result<()> {
+    let moves: String = record.moves.iter().map(|column| (column + 1).to_string()).collect::<Vec<_>>().join(",");
+    let turn = match record.turn {
+        Color::Red => "red",
+        Color::Yellow => "yellow",
+    };
+    writeln!(out, r#"{{"moves":"{moves}","position":"{}","turn":"{turn}","score":{},"eval":{},"move":{},"result":{result}}}"#,
+        record.position, values(&record.score), values(&record.eval), record.column + 1)
+}
+
+pub fn run(options: &SelfPlayOptions) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(&options.output)?);
+    let mut rng = Rng::new(options.seed);
+    let mut positions = 0;
+
+    for number in 1..=options.games {
+        let mut game = Game::new(options.width, options.height, options.k);
+        for column in tournament::opening(options.width, options.height, options.k, options.opening_moves, &mut rng) {
+            let _ = game.run(column);
+        }
+
+        let mut records = Vec::new();
+        let state = loop {
+            let player = match game.turn() {
+                Color::Red => &options.red,
+                Color::Yellow => &options.yellow,
+            };
+            let (eval, move_list) = player.search(&mut game).expect("self-play games are played between engines");
+            let column = *move_list.last().unwrap();
+
+            records.push(Record {
+                moves: game.move_list().to_vec(),
+                position: position(&game),
+                turn: game.turn(),
+                score: game.last_score().clone(),
+                eval,
+                column,
+            });
+
+            match game.run(column) {
+                Ok(GameState::InProgress) => {},
+                Ok(state) => break state,
+                Err(_) => panic!("engine played an illegal move"),
+            }
+        };
+
+        let result = match state {
+            GameState::Win(Color::Red) => 1.0,
+            GameState::Win(Color::Yellow) => 0.0,
+            _ => 0.5,
+        };
+        for record in &records {
+            write_record(&mut out, record, result)?;
+        }
+
+        positions += records.len();
+        println!("Game {number}/{}: {result} after {} moves, {positions} positions", options.games, game.move_list().len());
+    }
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tune;
+
+    fn record(game: &Game, column: usize) -> Record {
+        Record {
+            moves: game.move_list().to_vec(),
+            position: position(game),
+            turn: game.turn(),
+            score: game.last_score().clone(),
+            eval: game.last_score().clone(),
+            column,
+        }
+    }
+
+    #[test]
+    fn records_round_trip_through_the_tune_loader() {
+        let mut game = Game::new(7, 6, 4);
+        let mut out = Vec::new();
+        let mut scores = Vec::new();
+        for (column, result) in [(3, 1.0), (3, 0.0), (2, 0.5)] {
+            game.run(column).unwrap();
+            write_record(&mut out, &record(&game, 4), result).unwrap();
+            scores.push((game.last_score().values().to_vec(), result));
+        }
+
+        let text = String::from_utf8(out.clone()).unwrap();
+        assert!(text.lines().next().unwrap().starts_with(r#"{"moves":"4","position":"_______/_______/_______/_______/_______/___X___","turn":"yellow","#));
+
+        let samples = tune::read_samples(&out[..]).unwrap();
+        assert_eq!(samples.len(), scores.len());
+        for (sample, (score, result)) in samples.iter().zip(&scores) {
+            let counts: Vec<f64> = score.iter().map(|&count| count as f64).collect();
+            assert!(counts.iter().any(|&count| count != 0.0));
+            assert_eq!(sample.counts, counts);
+            assert_eq!(sample.result, *result);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::board::{Color, Game};
+
+const RED: &str = "#d62828";
+const YELLOW: &str = "#f4c20d";
+const BOARD: &str = "#1f4e9c";
+const HOLE: &str = "#ffffff";
+const HIGHLIGHT: &str = "#2ecc40";
+const TEXT: &str = "#333333";
+
+#[derive(Clone, Debug)]
+pub struct SvgOptions {
+    pub cell_size: usize,
+    pub labels: bool,
+    pub highlight_winning_line: bool,
+    pub highlight_last_move: bool,
+    // One entry per column, `None` for columns without an evaluation (e.g. full columns)
+    pub column_evals: Option<Vec<Option<i32>>>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 60,
+            labels: true,
+            highlight_winning_line: true,
+            highlight_last_move: true,
+            column_evals: None,
+        }
+    }
+}
+
+pub struct Svg<'a> {
+    game: &'a Game,
+    options: &'a SvgOptions,
+}
+
+impl<'a> Svg<'a> {
+    pub fn new(game: &'a Game, options: &'a SvgOptions) -> Self {
+        Self { game, options }
+    }
+
+    fn label_margin(&self) -> usize {
+        if self.options.labels { self.options.cell_size / 2 } else { 0 }
+    }
+
+    fn eval_margin(&self) -> usize {
+        if self.options.column_evals.is_some() { self.options.cell_size / 2 } else { 0 }
+    }
+
+    // Center of a cell, row 0 is the bottom row
+    fn center(&self, column: usize, row: usize) -> (usize, usize) {
+        let cell = self.options.cell_size;
+        let x = self.label_margin() + column * cell + cell / 2;
+        let y = self.eval_margin() + (self.game.height() - row - 1) * cell + cell / 2;
+        (x, y)
+    }
+}
+
+impl Display for Svg<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let game = self.game;
+        let cell = self.options.cell_size;
+        let radius = cell * 2 / 5;
+        let font_size = cell / 3;
+        let label_margin = self.label_margin();
+        let eval_margin = self.eval_margin();
+        let board_width = game.width() * cell;
+        let board_height = game.height() * cell;
+        let width = label_margin + board_width;
+        let height = eval_margin + board_height + label_margin;
+
+        writeln!(f, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#)?;
+        writeln!(f, r#"<rect x="{label_margin}" y="{eval_margin}" width="{board_width}" height="{board_height}" rx="{}" fill="{BOARD}"/>"#, cell / 8)?;
+
+        for column in 0..game.width() {
+            for row in 0..game.height() {
+                let (x, y) = self.center(column, row);
+                let fill = match game.get(column, row) {
+                    Some(Color::Red) => RED,
+                    Some(Color::Yellow) => YELLOW,
+                    None => HOLE,
+                };
+                writeln!(f, r#"<circle cx="{x}" cy="{y}" r="{radius}" fill="{fill}"/>"#)?;
+            }
+        }
+
+        if self.options.highlight_last_move {
+            if let Some((column, row)) = game.last_move() {
+                let (x, y) = self.center(column, row);
+                writeln!(f, r#"<circle cx="{x}" cy="{y}" r="{}" fill="{HOLE}" fill-opacity="0.8"/>"#, radius / 4)?;
+            }
+        }
+
+        if self.options.highlight_winning_line {
+            if let Some(line) = game.winning_line() {
+                for &(column, row) in &line {
+                    let (x, y) = self.center(column, row);
+                    writeln!(f, r#"<circle cx="{x}" cy="{y}" r="{radius}" fill="none" stroke="{HIGHLIGHT}" stroke-width="{}"/>"#, cell / 12)?;
+                }
+                let (x1, y1) = self.center(line[0].0, line[0].1);
+                let (x2, y2) = self.center(line[line.len() - 1].0, line[line.len() - 1].1);
+                writeln!(f, r#"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" stroke="{HIGHLIGHT}" stroke-width="{}" stroke-linecap="round"/>"#, cell / 10)?;
+            }
+        }
+
+        let text = |f: &mut Formatter<'_>, x: usize, y: usize, content: &dyn Display| {
+            writeln!(f, r#"<text x="{x}" y="{y}" font-family="sans-serif" font-size="{font_size}" fill="{TEXT}" text-anchor="middle" dominant-baseline="central">{content}</text>"#)
+        };
+
+        if self.options.labels {
+            for column in 0..game.width() {
+                let (x, _) = self.center(column, 0);
+                text(f, x, eval_margin + board_height + label_margin / 2, &(column + 1))?;
+            }
+            for row in 0..game.height() {
+                let (_, y) = self.center(0, row);
+                text(f, label_margin / 2, y, &(row + 1))?;
+            }
+        }
+
+        if let Some(evals) = &self.options.column_evals {
+            for (column, eval) in evals.iter().enumerate().take(game.width()) {
+                let Some(eval) = eval else { continue };
+                let (x, _) = self.center(column, 0);
+                text(f, x, eval_margin / 2, eval)?;
+            }
+        }
+
+        writeln!(f, "</svg>")
+    }
+}
+
+pub fn render(game: &Game, options: &SvgOptions) -> String {
+    Svg::new(game, options).to_string()
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Instant,
+};
+
+use crate::{
+    board::{Color, Game, GameState},
+    eval::Evaluator,
+};
+
+const MAGIC: &[u8; 4] = b"MNKT";
+const VERSION: u8 = 1;
+
+// Loaded at startup with --tablebase
+static LOADED: OnceLock<Tablebase> = OnceLock::new();
+
+pub struct TablebaseOptions {
+    pub width: usize,
+    pub height: usize,
+    pub k: usize,
+    // Moves leading to the root, the table covers every position reachable from it
+    pub moves: Vec<usize>,
+    pub output: PathBuf,
+}
+
+// Exact result for the side to move, with the number of moves until the game ends
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value {
+    Win(u8),
+    Loss(u8),
+    Draw,
+}
+
+impl Value {
+    // Draw as 0, wins below 128 and losses from 128 on
+    fn encode(self) -> u8 {
+        match self {
+            Value::Draw => 0,
+            Value::Win(plies) => plies,
+            Value::Loss(plies) => 128 + plies,
+        }
+    }
+
+    fn decode(byte: u8) -> Self {
+        match byte {
+            0 => Value::Draw,
+            1..128 => Value::Win(byte),
+            _ => Value::Loss(byte - 128),
+        }
+    }
+
+    // The value of the parent position, one move further from the end
+    fn parent(self) -> Self {
+        match self {
+            Value::Win(plies) => Value::Loss(plies + 1),
+            Value::Loss(plies) => Value::Win(plies + 1),
+            Value::Draw => Value::Draw,
+        }
+    }
+
+    // Orders values from the side to move's point of view: quick wins, draws, slow losses
+    fn rank(self) -> i32 {
+        match self {
+            Value::Win(plies) => 1000 - plies as i32,
+            Value::Draw => 0,
+            Value::Loss(plies) => plies as i32 - 1000,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Win(plies) => write!(f, "win in {}", plies.div_ceil(2)),
+            Value::Loss(plies) => write!(f, "loss in {}", plies / 2),
+            Value::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+// Bitboard with one extra bit on top of every column, so no line wraps around to the next one
+#[derive(Clone, Copy)]
+struct Bits {
+    width: usize,
+    height: usize,
+    k: usize,
+    red: u64,
+    mask: u64,
+}
+
+impl Bits {
+    fn fits(width: usize, height: usize) -> bool {
+        width * (height + 1) <= 64 && width * height < 128
+    }
+
+    fn from_game(game: &Game) -> Self {
+        let mut bits = Self { width: game.width(), height: game.height(), k: game.k(), red: 0, mask: 0 };
+        for column in 0..game.width() {
+            for row in 0..game.height() {
+                let Some(color) = game.get(column, row) else { break };
+                let bit = bits.bit(column, row);
+                bits.mask |= bit;
+                if color == Color::Red { bits.red |= bit }
+            }
+        }
+        bits
+    }
+
+    fn bit(&self, column: usize, row: usize) -> u64 {
+        1 << (column * (self.height + 1) + row)
+    }
+
+    fn bottom(&self) -> u64 {
+        (0..self.width).map(|column| self.bit(column, 0)).sum()
+    }
+
+    // The occupied cells plus one marker above every column, which tells the heights apart
+    fn key(&self) -> u64 {
+        (self.mask + self.bottom()) | self.red
+    }
+
+    fn with_key(&self, key: u64) -> Self {
+        let mut bits = *self;
+        bits.mask = 0;
+        for column in 0..self.width {
+            let height = (0..=self.height).rev().find(|&row| key & self.bit(column, row) != 0).unwrap_or(0);
+            bits.mask |= (0..height).map(|row| self.bit(column, row)).sum::<u64>();
+        }
+        bits.red = key & bits.mask;
+        bits
+    }
+
+    fn chips(&self) -> usize {
+        self.mask.count_ones() as usize
+    }
+
+    fn turn(&self) -> Color {
+        if self.chips().is_multiple_of(2) { Color::Red } else { Color::Yellow }
+    }
+
+    fn stones(&self, color: Color) -> u64 {
+        match color {
+            Color::Red => self.red,
+            Color::Yellow => self.mask & !self.red,
+        }
+    }
+
+    fn playable(&self, column: usize) -> bool {
+        self.mask & self.bit(column, self.height - 1) == 0
+    }
+
+    fn play(&self, column: usize) -> Self {
+        let column_mask: u64 = (0..self.height).map(|row| self.bit(column, row)).sum();
+        let stone = (self.mask + self.bit(column, 0)) & column_mask;
+        let mut next = *self;
+        next.mask |= stone;
+        if self.turn() == Color::Red { next.red |= stone }
+        next
+    }
+
+    fn wins(&self, color: Color) -> bool {
+        let stones = self.stones(color);
+        [1, self.height, self.height + 1, self.height + 2].into_iter().any(|shift| {
+            (1..self.k).fold(stones, |line, i| line & (stones >> (shift * i))) != 0
+        })
+    }
+
+    fn full(&self) -> bool {
+        self.chips() == self.width * self.height
+    }
+}
+
+pub struct Tablebase {
+    width: usize,
+    height: usize,
+    k: usize,
+    root_chips: usize,
+    // One sorted layer of keys and values per number of chips, starting at the root
+    layers: Vec<(Vec<u64>, Vec<u8>)>,
+}
+
+impl Tablebase {
+    pub fn positions(&self) -> usize {
+        self.layers.iter().map(|(keys, _)| keys.len()).sum()
+    }
+
+    fn covers(&self, game: &Game) -> bool {
+        (game.width(), game.height(), game.k()) == (self.width, self.height, self.k)
+            && game.move_list().len() >= self.root_chips
+    }
+
+    // None for positions outside the table and for finished games
+    pub fn probe(&self, game: &Game) -> Option<Value> {
+        if !self.covers(game) || !matches!(game.game_state(), GameState::InProgress) {
+            return None;
+        }
+        let bits = Bits::from_game(game);
+        self.lookup(bits.chips(), bits.key())
+    }
+
+    fn lookup(&self, chips: usize, key: u64) -> Option<Value> {
+        let (keys, values) = self.layers.get(chips.checked_sub(self.root_chips)?)?;
+        let index = keys.binary_search(&key).ok()?;
+        Some(Value::decode(values[index]))
+    }
+
+    // The best column with its value, preferring the quickest win and the slowest loss
+    pub fn best_move(&self, game: &Game) -> Option<(usize, Value)> {
+        self.probe(game)?;
+        let bits = Bits::from_game(game);
+        (0..bits.width)
+            .filter(|&column| bits.playable(column))
+            .map(|column| (column, self.child_value(&bits, column)))
+            .max_by_key(|&(column, value)| (value.rank(), -(column.abs_diff(bits.width / 2) as i32)))
+    }
+
+    fn child_value(&self, bits: &Bits, column: usize) -> Value {
+        let child = bits.play(column);
+        if child.wins(bits.turn()) {
+            Value::Win(1)
+        } else if child.full() {
+            Value::Draw
+        } else {
+            self.lookup(child.chips(), child.key()).expect("children of covered positions are covered").parent()
+        }
+    }
+
+    // Continues a line in play order with perfect play for as long as its positions are in the table
+    pub fn extend(&self, game: &mut Game, line: &mut Vec<usize>) {
+        for &column in line.iter() {
+            game.run_unchecked(column);
+        }
+        while let Some((column, _)) = self.best_move(game) {
+            line.push(column);
+            if !matches!(game.run_unchecked(column), Some(GameState::InProgress)) { break }
+        }
+        for _ in 0..line.len() {
+            game.undo_unchecked();
+        }
+    }
+
+    pub fn generate(options: &TablebaseOptions) -> io::Result<Self> {
+        let (width, height, k) = (options.width, options.height, options.k);
+        if !Bits::fits(width, height) {
+            return Err(io::Error::other("tablebases need width * (height + 1) of at most 64"));
+        }
+        let mut game = Game::new(width, height, k);
+        for &column in &options.moves {
+            if !matches!(game.run(column), Ok(GameState::InProgress)) {
+                return Err(io::Error::other("the moves must be legal and leave the game in progress"));
+            }
+        }
+
+        let root = Bits::from_game(&game);
+        let mut table = Self { width, height, k, root_chips: root.chips(), layers: Vec::new() };
+
+        // Every reachable position that is not decided yet, layer by layer
+        let start = Instant::now();
+        let mut layer = vec![root.key()];
+        while !layer.is_empty() {
+            let mut next = HashSet::new();
+            for &key in &layer {
+                let bits = root.with_key(key);
+                for column in (0..width).filter(|&column| bits.playable(column)) {
+                    let child = bits.play(column);
+                    if !child.wins(bits.turn()) && !child.full() {
+                        next.insert(child.key());
+                    }
+                }
+            }
+            println!("{:>3} chips: {:>10} positions", root.chips() + table.layers.len(), layer.len());
+            let values = vec![0; layer.len()];
+            table.layers.push((layer, values));
+            layer = next.into_iter().collect();
+            layer.sort_unstable();
+        }
+
+        // Retrograde analysis from the fullest layer back to the root, every move adds a chip
+        for index in (0..table.layers.len()).rev() {
+            let keys = std::mem::take(&mut table.layers[index].0);
+            let values: Vec<u8> = keys.iter()
+                .map(|&key| {
+                    let bits = root.with_key(key);
+                    (0..width)
+                        .filter(|&column| bits.playable(column))
+                        .map(|column| table.child_value(&bits, column))
+                        .max_by_key(|value| value.rank())
+                        .unwrap()
+                        .encode()
+                })
+                .collect();
+            table.layers[index] = (keys, values);
+        }
+
+        let root_value = table.lookup(root.chips(), root.key()).unwrap();
+        println!("{} positions in {:.1} s, the root is a {root_value}", table.positions(), start.elapsed().as_secs_f64());
+        Ok(table)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    // Keys are stored as varint deltas within each layer, values as one byte each
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, self.width as u8, self.height as u8, self.k as u8, self.root_chips as u8]);
+        bytes.extend((self.layers.len() as u32).to_le_bytes());
+
+        for (keys, values) in &self.layers {
+            bytes.extend((keys.len() as u32).to_le_bytes());
+            let mut previous = 0;
+            for &key in keys {
+                let mut delta = key - previous;
+                previous = key;
+                while delta >= 0x80 {
+                    bytes.push(delta as u8 | 0x80);
+                    delta >>= 7;
+                }
+                bytes.push(delta as u8);
+            }
+            bytes.extend(values);
+        }
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut input = bytes;
+        let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "the tablebase is corrupt");
+        let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "the tablebase is truncated");
+
+        let mut header = [0; 13];
+        input.read_exact(&mut header).map_err(|_| truncated())?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(io::Error::other("not a connect-mnk tablebase"));
+        }
+        let [width, height, k, root_chips] = [5, 6, 7, 8].map(|index| header[index] as usize);
+        let layer_count = u32::from_le_bytes(header[9..13].try_into().unwrap());
+        // The bitboards shift by these, so a corrupt size must not get any further
+        if width == 0 || height == 0 || !Bits::fits(width, height) || k == 0 || root_chips > width * height {
+            return Err(corrupt());
+        }
+
+        let mut layers = Vec::new();
+        for _ in 0..layer_count {
+            let mut count = [0; 4];
+            input.read_exact(&mut count).map_err(|_| truncated())?;
+            let count = u32::from_le_bytes(count) as usize;
+
+            let mut keys = Vec::with_capacity(count);
+            let mut previous = 0u64;
+            for _ in 0..count {
+                let mut delta = 0u64;
+                let mut shift = 0;
+                loop {
+                    let (&byte, rest) = input.split_first().ok_or_else(truncated)?;
+                    input = rest;
+                    if shift >= 64 { return Err(corrupt()) }
+                    delta |= ((byte & 0x7f) as u64) << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 { break }
+                }
+                previous = previous.checked_add(delta).ok_or_else(corrupt)?;
+                keys.push(previous);
+            }
+
+            let mut values = vec![0; count];
+            input.read_exact(&mut values).map_err(|_| truncated())?;
+            layers.push((keys, values));
+        }
+
+        Ok(Self { width, height, k, root_chips, layers })
+    }
+}
+
+pub fn set_loaded(table: Tablebase) {
+    let _ = LOADED.set(table);
+}
+
+pub fn loaded() -> Option<&'static Tablebase> {
+    LOADED.get()
+}
+
+pub fn run(options: &TablebaseOptions) -> io::Result<()> {
+    let table = Tablebase::generate(options)?;
+    table.save(&options.output)?;
+    let size = fs::metadata(&options.output)?.len();
+    println!("Wrote {} ({} bytes)", options.output.display(), size);
+    Ok(())
+}
+
+// Any evaluator, with exact results wherever the table covers the position
+pub struct Probed<'a, E> {
+    pub evaluator: &'a E,
+    pub table: &'a Tablebase,
+}
+
+impl<E: Evaluator> Evaluator for Probed<'_, E> {
+    type Value = E::Value;
+
+    fn evaluate(&self, game: &Game) -> E::Value {
+        self.evaluator.evaluate(game)
+    }
+
+    fn win(&self, game: &Game, winner: Color, plies: usize) -> E::Value {
+        self.evaluator.win(game, winner, plies)
+    }
+
+    fn draw(&self, game: &Game) -> E::Value {
+        self.evaluator.draw(game)
+    }
+
+    fn bounds(&self, game: &Game) -> (E::Value, E::Value) {
+        self.evaluator.bounds(game)
+    }
+
+    fn exact(&self, game: &Game) -> Option<E::Value> {
+        Some(match self.table.probe(game)? {
+            Value::Win(plies) => self.evaluator.win(game, game.turn(), plies as usize),
+            Value::Loss(plies) => self.evaluator.win(game, game.turn().other(), plies as usize),
+            Value::Draw => self.evaluator.draw(game),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn generate(moves: Vec<usize>) -> Tablebase {
+        Tablebase::generate(&TablebaseOptions { width: 4, height: 4, k: 3, moves, output: PathBuf::new() }).unwrap()
+    }
+
+    // Plain negamax over every line, checking the table at each position on the way
+    fn solve(game: &mut Game, table: &Tablebase, solved: &mut HashMap<u64, Value>) -> Value {
+        let key = Bits::from_game(game).key();
+        if let Some(&value) = solved.get(&key) {
+            return value;
+        }
+
+        let value = game.legal_moves().into_iter()
+            .map(|column| {
+                let value = match game.run_unchecked(column) {
+                    Some(GameState::Win(_)) => Value::Win(1),
+                    Some(GameState::Draw) => Value::Draw,
+                    _ => solve(game, table, solved).parent(),
+                };
+                game.undo_unchecked();
+                value
+            })
+            .max_by_key(|value| value.rank())
+            .unwrap();
+
+        assert_eq!(table.probe(game), Some(value), "{:?}", game.move_list());
+        solved.insert(key, value);
+        value
+    }
+
+    #[test]
+    fn probe_matches_exhaustive_search() {
+        let table = generate(Vec::new());
+        let mut solved = HashMap::new();
+        solve(&mut Game::new(4, 4, 3), &table, &mut solved);
+        assert_eq!(solved.len(), table.positions());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let table = generate(vec![1, 2]);
+        let loaded = Tablebase::from_bytes(&table.to_bytes()).unwrap();
+
+        assert_eq!((loaded.width, loaded.height, loaded.k, loaded.root_chips), (4, 4, 3, 2));
+        assert_eq!(loaded.layers, table.layers);
+        let mut game = Game::new(4, 4, 3);
+        game.run(1).unwrap();
+        game.run(2).unwrap();
+        assert_eq!(loaded.best_move(&game), table.best_move(&game));
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let bytes = generate(Vec::new()).to_bytes();
+
+        let mut wide = bytes.clone();
+        wide[5] = 200;
+        assert_eq!(Tablebase::from_bytes(&wide).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        let mut flat = bytes.clone();
+        flat[6] = 0;
+        assert_eq!(Tablebase::from_bytes(&flat).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(Tablebase::from_bytes(&bytes[..bytes.len() - 1]).err().unwrap().kind(), io::ErrorKind::UnexpectedEof);
+        assert!(Tablebase::from_bytes(b"GIF89a").is_err());
+    }
+}
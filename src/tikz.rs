@@ -0,0 +1,106 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::board::{Color, Game};
+
+#[derive(Clone, Debug)]
+pub struct TikzOptions {
+    // Scale of the picture, one unit per cell
+    pub scale: f32,
+    pub red: String,
+    pub yellow: String,
+    pub board: String,
+    pub labels: bool,
+    pub move_numbers: bool,
+    pub highlight_threats: bool,
+}
+
+impl Default for TikzOptions {
+    fn default() -> Self {
+        Self {
+            scale: 0.8,
+            red: "red!80!black".into(),
+            yellow: "yellow!90!orange".into(),
+            board: "blue!60!black".into(),
+            labels: true,
+            move_numbers: false,
+            highlight_threats: false,
+        }
+    }
+}
+
+pub struct Tikz<'a> {
+    game: &'a Game,
+    options: &'a TikzOptions,
+}
+
+impl<'a> Tikz<'a> {
+    pub fn new(game: &'a Game, options: &'a TikzOptions) -> Self {
+        Self { game, options }
+    }
+
+    // Move number of every occupied cell, indexed like the board in column-major order
+    fn move_numbers(&self) -> Vec<Option<usize>> {
+        let game = self.game;
+        let mut numbers = vec![None; game.width() * game.height()];
+        let mut heights = vec![0; game.width()];
+        for (i, &column) in game.move_list().iter().enumerate() {
+            numbers[game.height() * column + heights[column]] = Some(i + 1);
+            heights[column] += 1;
+        }
+        numbers
+    }
+}
+
+impl Display for Tikz<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let game = self.game;
+        let options = self.options;
+        let (width, height) = (game.width(), game.height());
+        let numbers = self.move_numbers();
+
+        writeln!(f, "\\begin{{tikzpicture}}[scale={}]", options.scale)?;
+        writeln!(f, "  \\fill[{}, rounded corners=2pt] (0,0) rectangle ({width},{height});", options.board)?;
+
+        for column in 0..width {
+            for row in 0..height {
+                let (x, y) = (column as f32 + 0.5, row as f32 + 0.5);
+                let (fill, text) = match game.get(column, row) {
+                    Some(Color::Red) => (options.red.as_str(), "white"),
+                    Some(Color::Yellow) => (options.yellow.as_str(), "black"),
+                    None => ("white", "black"),
+                };
+                writeln!(f, "  \\fill[{fill}] ({x},{y}) circle (0.4);")?;
+
+                if options.move_numbers {
+                    if let Some(number) = numbers[height * column + row] {
+                        writeln!(f, "  \\node[{text}, font=\\small] at ({x},{y}) {{{number}}};")?;
+                    }
+                }
+            }
+        }
+
+        if options.highlight_threats {
+            for (color, draw) in [(Color::Red, &options.red), (Color::Yellow, &options.yellow)] {
+                for (column, row) in game.threats(color) {
+                    let (x, y) = (column as f32 + 0.5, row as f32 + 0.5);
+                    writeln!(f, "  \\draw[{draw}, very thick] ({x},{y}) circle (0.25);")?;
+                }
+            }
+        }
+
+        if options.labels {
+            for column in 0..width {
+                writeln!(f, "  \\node at ({},-0.4) {{{}}};", column as f32 + 0.5, column + 1)?;
+            }
+            for row in 0..height {
+                writeln!(f, "  \\node at (-0.4,{}) {{{}}};", row as f32 + 0.5, row + 1)?;
+            }
+        }
+
+        writeln!(f, "\\end{{tikzpicture}}")
+    }
+}
+
+pub fn render(game: &Game, options: &TikzOptions) -> String {
+    Tikz::new(game, options).to_string()
+}
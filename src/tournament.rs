@@ -0,0 +1,293 @@
+use crate::{board::{Color, Game, GameState}, rng::Rng, Player};
+
+pub struct TournamentOptions {
+    pub width: usize,
+    pub height: usize,
+    pub k: usize,
+    pub engines: Vec<Player>,
+    // Play the first engine against every other one instead of a round robin
+    pub gauntlet: bool,
+    // Openings per pairing, each one is played with both colours
+    pub rounds: usize,
+    pub opening_moves: usize,
+    pub seed: u64,
+    pub elo0: f64,
+    pub elo1: f64,
+    // Both the false positive and the false negative rate of the SPRT
+    pub error_rate: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Record {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Sprt {
+    AcceptH0,
+    AcceptH1,
+    Continue,
+}
+
+impl Record {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    fn add(&mut self, other: Record) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+
+    fn reversed(&self) -> Self {
+        Self { wins: self.losses, draws: self.draws, losses: self.wins }
+    }
+
+    // Mean and variance of the score of a single game
+    fn moments(&self) -> (f64, f64) {
+        let games = self.games() as f64;
+        let mean = self.points() / games;
+        let variance = (self.wins as f64 * (1.0 - mean).powi(2)
+            + self.draws as f64 * (0.5 - mean).powi(2)
+            + self.losses as f64 * mean.powi(2)) / games;
+        // Records without any spread, such as all wins, get about the variance one more draw would
+        // give instead of none, which no test could ever decide on
+        (mean, variance.max(1.0 / (4.0 * games)))
+    }
+
+    // Elo difference with the bounds of its 95% confidence interval
+    pub fn elo(&self) -> (f64, f64, f64) {
+        if self.games() == 0 { return (0.0, f64::NEG_INFINITY, f64::INFINITY) }
+        let (mean, variance) = self.moments();
+        let margin = 1.96 * (variance / self.games() as f64).sqrt();
+        // Scores outside 0..=1 would give NaN, the bounds become infinite instead
+        (elo(mean), elo((mean - margin).max(0.0)), elo((mean + margin).min(1.0)))
+    }
+
+    // Log-likelihood ratio of H1 (elo1) against H0 (elo0), using the normal approximation
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        if self.games() == 0 { return 0.0 }
+        let (mean, variance) = self.moments();
+        let (score0, score1) = (expected_score(elo0), expected_score(elo1));
+        self.games() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    pub fn sprt(&self, elo0: f64, elo1: f64, error_rate: f64) -> Sprt {
+        let llr = self.llr(elo0, elo1);
+        let lower = (error_rate / (1.0 - error_rate)).ln();
+        let upper = ((1.0 - error_rate) / error_rate).ln();
+        if llr >= upper {
+            Sprt::AcceptH1
+        } else if llr <= lower {
+            Sprt::AcceptH0
+        } else {
+            Sprt::Continue
+        }
+    }
+}
+
+fn elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn pairings(engines: usize, gauntlet: bool) -> Vec<(usize, usize)> {
+    if gauntlet {
+        (1..engines).map(|other| (0, other)).collect()
+    } else {
+        (0..engines).flat_map(|a| (a + 1..engines).map(move |b| (a, b))).collect()
+    }
+}
+
+// Random legal moves that do not end the game, or no moves if none are found
+pub fn opening(width: usize, height: usize, k: usize, length: usize, rng: &mut Rng) -> Vec<usize> {
+    const ATTEMPTS: usize = 1000;
+
+    'retry: for _ in 0..ATTEMPTS {
+        let mut game = Game::new(width, height, k);
+        let mut moves = Vec::new();
+        for _ in 0..length {
+            let Some(&column) = rng.choose(&game.legal_moves()) else { continue 'retry };
+            if !matches!(game.run(column), Ok(GameState::InProgress)) { continue 'retry }
+            moves.push(column);
+        }
+        return moves;
+    }
+
+    Vec::new()
+}
+
+// Result from Red's point of view
+fn play_game(options: &TournamentOptions, opening: &[usize], red: &Player, yellow: &Player) -> Record {
+    let mut game = Game::new(options.width, options.height, options.k);
+    for &column in opening {
+        let _ = game.run(column);
+    }
+
+    loop {
+        let player = match game.turn() {
+            Color::Red => red,
+            Color::Yellow => yellow,
+        };
+        let column = player.engine_move(&mut game).expect("tournaments are played between engines");
+        match game.run(column) {
+            Ok(GameState::InProgress) => {},
+            Ok(GameState::Win(Color::Red)) => return Record { wins: 1, draws: 0, losses: 0 },
+            Ok(GameState::Win(Color::Yellow)) => return Record { wins: 0, draws: 0, losses: 1 },
+            Ok(GameState::Draw) => return Record { wins: 0, draws: 1, losses: 0 },
+            // An engine that plays an illegal move forfeits
+            Err(_) => return match game.turn() {
+                Color::Red => Record { wins: 0, draws: 0, losses: 1 },
+                Color::Yellow => Record { wins: 1, draws: 0, losses: 0 },
+            },
+        }
+    }
+}
+
+fn verdict(sprt: Sprt) -> &'static str {
+    match sprt {
+        Sprt::AcceptH0 => "H0 accepted",
+        Sprt::AcceptH1 => "H1 accepted",
+        Sprt::Continue => "inconclusive",
+    }
+}
+
+fn label(engines: &[Player], index: usize) -> String {
+    format!("#{} {}", index + 1, engines[index])
+}
+
+fn moves_string(moves: &[usize]) -> String {
+    moves.iter().map(|column| (column + 1).to_string()).collect::<Vec<_>>().join(" ")
+}
+
+pub fn run(options: &TournamentOptions) {
+    let engines = &options.engines;
+    let pairings = pairings(engines.len(), options.gauntlet);
+    let mut rng = Rng::new(options.seed);
+    let mut results = vec![Record::default(); pairings.len()];
+    let mut number = 0;
+
+    for _ in 0..options.rounds {
+        let opening = opening(options.width, options.height, options.k, options.opening_moves, &mut rng);
+        for (&(a, b), result) in pairings.iter().zip(&mut results) {
+            // Each engine plays the opening once with each colour
+            for swap in [false, true] {
+                let (red, yellow) = if swap { (b, a) } else { (a, b) };
+                let record = play_game(options, &opening, &engines[red], &engines[yellow]);
+                result.add(if swap { record.reversed() } else { record });
+
+                number += 1;
+                let outcome = match (record.wins, record.losses) {
+                    (1, _) => "1-0",
+                    (_, 1) => "0-1",
+                    _ => "½-½",
+                };
+                println!("Game {number}: {} vs {} [{}] {outcome}",
+                    label(engines, red), label(engines, yellow), moves_string(&opening));
+            }
+        }
+    }
+
+    // Long engine options widen the name columns
+    let width = (0..engines.len()).map(|index| label(engines, index).chars().count()).max().unwrap_or(0).max(20);
+
+    println!();
+    println!("{:<width$} {:<width$} {:>5} {:>5} {:>5} {:>7} {:>22}  {:>8}  SPRT", "Engine", "Opponent", "W", "D", "L", "Score", "Elo (95%)", "LLR");
+    for (&(a, b), result) in pairings.iter().zip(&results) {
+        let (estimate, lower, upper) = result.elo();
+        println!("{:<width$} {:<width$} {:>5} {:>5} {:>5} {:>7.1} {:>+7.0} [{:>+6.0}, {:>+6.0}]  {:>8.2}  {}",
+            label(engines, a), label(engines, b), result.wins, result.draws, result.losses, result.points(),
+            estimate, lower, upper, result.llr(options.elo0, options.elo1),
+            verdict(result.sprt(options.elo0, options.elo1, options.error_rate)));
+    }
+
+    let mut standings = vec![Record::default(); engines.len()];
+    for (&(a, b), result) in pairings.iter().zip(&results) {
+        standings[a].add(*result);
+        standings[b].add(result.reversed());
+    }
+    let mut order: Vec<usize> = (0..engines.len()).collect();
+    order.sort_by(|&a, &b| standings[b].points().total_cmp(&standings[a].points()));
+
+    println!();
+    println!("{:<4} {:<width$} {:>5} {:>5} {:>5} {:>5} {:>7} {:>22}", "Rank", "Engine", "Games", "W", "D", "L", "Points", "Elo vs field (95%)");
+    for (rank, &index) in order.iter().enumerate() {
+        let record = standings[index];
+        let (estimate, lower, upper) = record.elo();
+        println!("{:<4} {:<width$} {:>5} {:>5} {:>5} {:>5} {:>7.1} {:>+7.0} [{:>+6.0}, {:>+6.0}]",
+            rank + 1, label(engines, index), record.games(), record.wins, record.draws, record.losses,
+            record.points(), estimate, lower, upper);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(wins: usize, draws: usize, losses: usize) -> Record {
+        Record { wins, draws, losses }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 0.01, "{actual} is not {expected}");
+    }
+
+    #[test]
+    fn elo_with_confidence_interval() {
+        let (elo, lower, upper) = record(60, 20, 20).elo();
+        assert_close(elo, 147.19);
+        assert_close(lower, 86.22);
+        assert_close(upper, 218.25);
+
+        let (elo, lower, upper) = record(20, 20, 60).elo();
+        assert_close(elo, -147.19);
+        assert_close(lower, -218.25);
+        assert_close(upper, -86.22);
+
+        let (elo, lower, upper) = record(30, 40, 30).elo();
+        assert_close(elo, 0.0);
+        assert_close(lower, -53.16);
+        assert_close(upper, 53.16);
+
+        assert_eq!(record(0, 0, 0).elo(), (0.0, f64::NEG_INFINITY, f64::INFINITY));
+    }
+
+    #[test]
+    fn elo_of_records_without_spread() {
+        let (elo, lower, upper) = record(10, 0, 0).elo();
+        assert_eq!((elo, upper), (f64::INFINITY, f64::INFINITY));
+        assert_close(lower, 385.59);
+
+        let (elo, lower, upper) = record(0, 50, 0).elo();
+        assert_close(elo, 0.0);
+        assert_close(lower, -13.63);
+        assert_close(upper, 13.63);
+    }
+
+    #[test]
+    fn llr_and_sprt() {
+        assert_close(record(60, 20, 20).llr(0.0, 10.0), 1.73);
+        assert_close(record(20, 20, 60).llr(0.0, 10.0), -1.86);
+        assert_close(record(30, 40, 30).llr(0.0, 10.0), -0.07);
+        assert_eq!(record(0, 0, 0).llr(0.0, 10.0), 0.0);
+        assert!(matches!(record(60, 20, 20).sprt(0.0, 10.0, 0.05), Sprt::Continue));
+
+        // The bounds are ln(1/19) and ln(19), about -2.94 and 2.94
+        assert_close(record(10, 0, 0).llr(0.0, 10.0), 2.84);
+        assert!(matches!(record(10, 0, 0).sprt(0.0, 10.0, 0.05), Sprt::Continue));
+        assert!(matches!(record(11, 0, 0).sprt(0.0, 10.0, 0.05), Sprt::AcceptH1));
+        assert_close(record(0, 100, 0).llr(0.0, 10.0), -4.14);
+        assert!(matches!(record(0, 100, 0).sprt(0.0, 10.0, 0.05), Sprt::AcceptH0));
+        assert!(matches!(record(0, 0, 11).sprt(0.0, 10.0, 0.05), Sprt::AcceptH0));
+    }
+}
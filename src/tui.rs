@@ -0,0 +1,281 @@
+use std::{
+    fmt::Write as _,
+    io::{self, stdin, stdout, IsTerminal, Read, Write},
+    ops::ControlFlow,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    ansi::{BOLD, DIM, HIGHLIGHT, RED, RESET, YELLOW},
+    board::{Color, Game, GameState, SearchStats},
+    eval, Player,
+};
+
+const FALL_DELAY: Duration = Duration::from_millis(35);
+const PANEL_LINES: usize = 12;
+
+enum Key {
+    Left,
+    Right,
+    Drop,
+    Undo,
+    Quit,
+    Other,
+}
+
+// Raw mode through stty and the alternate screen, both restored on drop
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        if !stdin().is_terminal() || !stdout().is_terminal() {
+            return Err(io::Error::other("the terminal UI needs an interactive terminal"));
+        }
+
+        let saved = stty(&["-g"])?;
+        // Reads give up after a tenth of a second, so a lone escape is not mistaken for the
+        // start of an arrow key
+        stty(&["raw", "-echo", "min", "0", "time", "1"])?;
+        print!("\x1b[?1049h\x1b[?25l");
+        stdout().flush()?;
+
+        Ok(Self { saved: saved.trim().to_string() })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(arguments: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(arguments).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// None if no byte arrives in time
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    Ok((input.read(&mut byte)? == 1).then_some(byte[0]))
+}
+
+fn read_key() -> io::Result<Key> {
+    let mut input = stdin().lock();
+    let first = loop {
+        if let Some(byte) = read_byte(&mut input)? { break byte }
+    };
+
+    Ok(match first {
+        b'\r' | b'\n' | b' ' => Key::Drop,
+        b'h' | b'a' => Key::Left,
+        b'l' | b'd' => Key::Right,
+        b'u' => Key::Undo,
+        b'q' | 3 => Key::Quit,
+        0x1b => match read_byte(&mut input)? {
+            Some(b'[') => match read_byte(&mut input)? {
+                Some(b'D') => Key::Left,
+                Some(b'C') => Key::Right,
+                _ => Key::Other,
+            },
+            _ => Key::Other,
+        },
+        _ => Key::Other,
+    })
+}
+
+fn chip(color: Color) -> String {
+    match color {
+        Color::Red => format!("{RED}●{RESET}"),
+        Color::Yellow => format!("{YELLOW}●{RESET}"),
+    }
+}
+
+struct Ui {
+    cursor: usize,
+    eval: String,
+    thinking: Vec<String>,
+    status: String,
+}
+
+impl Ui {
+    // Redraws the whole screen, optionally with a chip falling through `column` at `row`
+    fn draw(&self, game: &Game, falling: Option<(usize, usize, Color)>) -> io::Result<()> {
+        let mut frame = String::from("\x1b[2J");
+        let mut line = 1;
+        let put = |frame: &mut String, line: &mut usize, text: &str| {
+            let _ = write!(frame, "\x1b[{line};1H{text}");
+            *line += 1;
+        };
+
+        put(&mut frame, &mut line, &format!("{BOLD}connect-mnk{RESET}  {}x{}, {} in a row", game.width(), game.height(), game.k()));
+        line += 1;
+
+        let mut arrow = "  ".repeat(self.cursor);
+        arrow.push_str(&match game.turn() {
+            Color::Red => format!("{RED}▼{RESET}"),
+            Color::Yellow => format!("{YELLOW}▼{RESET}"),
+        });
+        put(&mut frame, &mut line, &arrow);
+
+        let winning_line = game.winning_line().unwrap_or_default();
+        let board_top = line;
+        for row in (0..game.height()).rev() {
+            let mut text = String::new();
+            for column in 0..game.width() {
+                let cell = match (falling, game.get(column, row)) {
+                    (Some((x, y, color)), _) if (x, y) == (column, row) => chip(color),
+                    (_, Some(color)) if winning_line.contains(&(column, row)) => format!("{HIGHLIGHT}{}", chip(color)),
+                    (_, Some(color)) => chip(color),
+                    (_, None) => format!("{DIM}·{RESET}"),
+                };
+                text.push_str(&cell);
+                text.push(' ');
+            }
+            put(&mut frame, &mut line, &text);
+        }
+        let numbers: String = (1..=game.width()).map(|column| format!("{:<2}", column % 10)).collect();
+        put(&mut frame, &mut line, &numbers);
+        line += 1;
+        put(&mut frame, &mut line, &self.status);
+        put(&mut frame, &mut line, &format!("{DIM}←/→ select  enter drop  u undo  q quit{RESET}"));
+
+        // Side panel with the move list, the evaluation and the engine's output
+        let panel = game.width() * 2 + 4;
+        let mut panel_lines = vec![format!("{BOLD}Moves{RESET}")];
+        let moves: Vec<String> = game.move_list()
+            .chunks(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                let pair: Vec<String> = pair.iter().map(|column| (column + 1).to_string()).collect();
+                format!("{:>3}. {}", i + 1, pair.join(" "))
+            })
+            .collect();
+        panel_lines.extend(moves.iter().skip(moves.len().saturating_sub(PANEL_LINES)).cloned());
+        panel_lines.push(String::new());
+        panel_lines.push(format!("{BOLD}Evaluation{RESET} {}", self.eval));
+        panel_lines.push(format!("{BOLD}Engine{RESET}"));
+        panel_lines.extend(self.thinking.iter().cloned());
+
+        for (i, text) in panel_lines.iter().enumerate() {
+            let _ = write!(frame, "\x1b[{};{panel}H{text}", board_top - 2 + i);
+        }
+
+        let mut out = stdout().lock();
+        out.write_all(frame.as_bytes())?;
+        out.flush()
+    }
+
+    fn animate(&self, game: &Game, column: usize) -> io::Result<()> {
+        let landing = (0..game.height()).find(|&row| game.get(column, row).is_none());
+        let Some(landing) = landing else { return Ok(()) };
+
+        for row in (landing..game.height()).rev() {
+            self.draw(game, Some((column, row, game.turn())))?;
+            thread::sleep(FALL_DELAY);
+        }
+        Ok(())
+    }
+}
+
+// The principal variation has the best move last
+fn thinking(title: &str, stats: Option<&SearchStats>, pv: &[usize]) -> Vec<String> {
+    let pv: Vec<String> = pv.iter().rev().map(|column| (column + 1).to_string()).collect();
+    let mut lines = vec![title.to_string()];
+    if let Some(stats) = stats {
+        lines.push(format!("depth {} nodes {}", stats.depth, stats.nodes));
+    }
+    lines.push(format!("pv {}", pv.join(" ")));
+    lines
+}
+
+fn describe(state: GameState) -> String {
+    match state {
+        GameState::Win(winner) => format!("{winner} wins! u to undo, q to quit"),
+        GameState::Draw => "Draw! u to undo, q to quit".to_string(),
+        GameState::InProgress => String::new(),
+    }
+}
+
+pub fn play(game: &mut Game, red: &Player, yellow: &Player) -> io::Result<()> {
+    let _terminal = Terminal::enter()?;
+    let mut ui = Ui {
+        cursor: game.width() / 2,
+        eval: format!("{:?}", game.last_score().values()),
+        thinking: Vec::new(),
+        status: String::new(),
+    };
+
+    loop {
+        let state = game.game_state();
+        let player = match game.turn() {
+            Color::Red => red,
+            Color::Yellow => yellow,
+        };
+
+        if !matches!(state, GameState::InProgress) || matches!(player, Player::Human) {
+            ui.status = match state {
+                GameState::InProgress => format!("{}'s turn", game.turn()),
+                state => describe(state),
+            };
+            ui.draw(game, None)?;
+
+            match read_key()? {
+                Key::Left => ui.cursor = ui.cursor.saturating_sub(1),
+                Key::Right => ui.cursor = (ui.cursor + 1).min(game.width() - 1),
+                Key::Undo => {
+                    game.undo();
+                    game.undo();
+                },
+                Key::Quit => return Ok(()),
+                Key::Drop if matches!(state, GameState::InProgress) => {
+                    if game.get(ui.cursor, game.height() - 1).is_some() {
+                        continue;
+                    }
+                    ui.animate(game, ui.cursor)?;
+                    let _ = game.run(ui.cursor);
+                },
+                Key::Drop | Key::Other => {},
+            }
+            continue;
+        }
+
+        ui.status = format!("{} is thinking...", game.turn());
+        ui.draw(game, None)?;
+
+        let title = format!("{player} ({})", game.turn());
+        let result = match player {
+            // Minimax reports every completed depth, which is shown while it searches deeper
+            Player::Computer(depth, evaluation) => {
+                let position = game.clone();
+                let mut error = Ok(());
+                let (score, pv, _) = eval::search_with_info(game, evaluation, *depth, &Default::default(), |stats, score, pv| {
+                    ui.eval = format!("{:?}", score.values());
+                    ui.thinking = thinking(&title, Some(stats), pv);
+                    error = ui.draw(&position, None);
+                    if error.is_err() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+                });
+                error?;
+                Some((score, pv))
+            },
+            _ => player.search(game),
+        };
+        let Some((score, pv)) = result else { continue };
+        let Some(&column) = pv.last() else { return Ok(()) };
+        ui.eval = format!("{:?}", score.values());
+        ui.thinking = thinking(&title, None, &pv);
+
+        ui.cursor = column;
+        ui.animate(game, column)?;
+        let _ = game.run(column);
+    }
+}
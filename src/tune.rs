@@ -0,0 +1,152 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+};
+
+use crate::eval::{Weighted, SCALE};
+
+pub struct TuneOptions {
+    pub data: PathBuf,
+    pub output: PathBuf,
+    // Largest step tried for a weight, halved whenever no weight improves
+    pub step: i64,
+    pub max_passes: usize,
+}
+
+// The chain counts of a selfplay record with the result of its game from Red's point of view
+pub struct Sample {
+    pub counts: Vec<f64>,
+    pub result: f64,
+}
+
+// The raw JSON value of a field in a flat object, enough for the records written by selfplay
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{name}\":"))? + name.len() + 3;
+    let rest = &line[start..];
+    let end = if rest.starts_with('[') {
+        rest.find(']')? + 1
+    } else {
+        rest.find([',', '}'])?
+    };
+    Some(&rest[..end])
+}
+
+fn parse_sample(line: &str) -> Option<Sample> {
+    let counts = field(line, "score")?.strip_prefix('[')?.strip_suffix(']')?
+        .split(',')
+        .map(|count| count.trim().parse().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    let result = field(line, "result")?.trim().parse().ok()?;
+    Some(Sample { counts, result })
+}
+
+pub fn read_samples(input: impl BufRead) -> io::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() { continue }
+        let sample = parse_sample(&line)
+            .ok_or_else(|| io::Error::other(format!("line {} is not a selfplay record", number + 1)))?;
+        if samples.first().is_some_and(|first: &Sample| first.counts.len() != sample.counts.len()) {
+            return Err(io::Error::other(format!("line {} has a different k than the lines before", number + 1)));
+        }
+        samples.push(sample);
+    }
+    if samples.is_empty() {
+        return Err(io::Error::other("the dataset is empty"));
+    }
+    Ok(samples)
+}
+
+fn sigmoid(value: f64) -> f64 {
+    1.0 / (1.0 + (-value).exp())
+}
+
+// Mean squared difference between the results and the win probabilities the weights predict
+fn error(samples: &[Sample], weights: &[i64]) -> f64 {
+    let total: f64 = samples.iter()
+        .map(|sample| {
+            let value: f64 = sample.counts.iter().zip(weights).map(|(count, &weight)| count * weight as f64).sum();
+            (sample.result - sigmoid(value / SCALE)).powi(2)
+        })
+        .sum();
+    total / samples.len() as f64
+}
+
+pub fn run(options: &TuneOptions) -> io::Result<()> {
+    let samples = read_samples(BufReader::new(File::open(&options.data)?))?;
+    let k = samples[0].counts.len();
+    let weights = Weighted::new(k).weights;
+    println!("{} positions, k = {k}, initial error {:.6}, weights {weights:?}", samples.len(), error(&samples, &weights));
+
+    let weights = tune(&samples, weights, options.step, options.max_passes);
+    fs::write(&options.output, Weighted { weights }.serialize(&options.data.display().to_string()))?;
+    println!("Wrote {}", options.output.display());
+    Ok(())
+}
+
+// Texel tuning: local search over integer weights, trying a step up and down for every weight
+fn tune(samples: &[Sample], mut weights: Vec<i64>, step: i64, max_passes: usize) -> Vec<i64> {
+    let k = weights.len();
+    let mut best = error(samples, &weights);
+    let mut step = step.max(1);
+    for pass in 1..=max_passes {
+        let mut improved = false;
+        // A chain of length k is a win, which the search scores without the weights
+        for index in 0..k.saturating_sub(1) {
+            for delta in [step, -step] {
+                weights[index] += delta;
+                let candidate = error(samples, &weights);
+                if candidate < best {
+                    best = candidate;
+                    improved = true;
+                    break;
+                }
+                weights[index] -= delta;
+            }
+        }
+        println!("Pass {pass}: step {step}, error {best:.6}, weights {weights:?}");
+
+        if !improved {
+            if step == 1 { break }
+            step /= 2;
+        }
+    }
+
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Red wins the games where it has more chains, whatever their length
+    const DATA: &str = r#"
+{"score":[4,1,0,0],"result":1}
+{"score":[-4,-1,0,0],"result":0}
+{"score":[3,2,0,0],"result":1}
+{"score":[-2,-2,0,0],"result":0}
+{"score":[1,-1,0,0],"result":0.5}
+{"score":[0,0,0,0],"result":0.5}
+"#;
+
+    #[test]
+    fn tuning_lowers_the_error() {
+        let samples = read_samples(DATA.as_bytes()).unwrap();
+        assert_eq!(samples.len(), 6);
+
+        let initial = Weighted::new(4).weights;
+        let tuned = tune(&samples, initial.clone(), 64, 20);
+        assert!(error(&samples, &tuned) < error(&samples, &initial));
+        // The weight of a whole row is never touched
+        assert_eq!(tuned[3], initial[3]);
+    }
+
+    #[test]
+    fn bad_data_is_rejected() {
+        assert!(read_samples("".as_bytes()).is_err());
+        assert!(read_samples(r#"{"score":[1,2],"result":"#.as_bytes()).is_err());
+        assert!(read_samples("{\"score\":[1,2],\"result\":1}\n{\"score\":[1,2,3],\"result\":1}".as_bytes()).is_err());
+    }
+}
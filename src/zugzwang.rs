@@ -0,0 +1,253 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    board::{Color, Game, Score},
+    eval::{ChainScore, Evaluator},
+};
+
+// Rows counted from 1 at the bottom, so the bottom row is odd
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    Odd,
+    Even,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Threat {
+    pub column: usize,
+    pub row: usize,
+    pub color: Color,
+    pub parity: Parity,
+    // The cell can be played right now
+    pub playable: bool,
+    // Lowest threat of its column that is not playable yet, the only one that matters once the
+    // column fills up
+    pub lowest: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win(Color),
+    Draw,
+}
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub threats: Vec<Threat>,
+    pub prediction: Option<Outcome>,
+}
+
+impl Analysis {
+    pub fn threats_of(&self, color: Color) -> impl Iterator<Item = &Threat> {
+        self.threats.iter().filter(move |threat| threat.color == color)
+    }
+}
+
+// Red moves first, so when the board fills up column by column Red gets the odd rows and Yellow
+// the even ones: Yellow can always answer on top of Red's move. The rules of thumb follow:
+//
+//   - the side to move wins with a playable threat, and loses against two playable threats
+//   - a single playable threat is blocked, and so is the threat directly on top of it
+//   - Red wins with an odd threat that is the lowest of its column
+//   - otherwise Yellow wins with such an even threat
+//   - otherwise the game is drawn
+//
+// This only holds for boards with an even height, on others the prediction is None unless a
+// threat can be played right away.
+pub fn analyse(game: &Game) -> Analysis {
+    let mut threats = Vec::new();
+    for color in [Color::Red, Color::Yellow] {
+        for (column, row) in game.threats(color) {
+            let parity = if row % 2 == 0 { Parity::Odd } else { Parity::Even };
+            let playable = row == 0 || game.get(column, row - 1).is_some();
+            threats.push(Threat { column, row, color, parity, playable, lowest: false });
+        }
+    }
+
+    let lowest: Vec<Option<usize>> = (0..game.width())
+        .map(|column| {
+            threats.iter()
+                .filter(|threat| threat.column == column && !threat.playable)
+                .map(|threat| threat.row)
+                .min()
+        })
+        .collect();
+    for threat in &mut threats {
+        threat.lowest = !threat.playable && lowest[threat.column] == Some(threat.row);
+    }
+
+    let prediction = predict(game, &threats);
+    Analysis { threats, prediction }
+}
+
+fn predict(game: &Game, threats: &[Threat]) -> Option<Outcome> {
+    let turn = game.turn();
+    let opponent = turn.other();
+    let playable = |color: Color| threats.iter().filter(|threat| threat.color == color && threat.playable).count();
+
+    if playable(turn) > 0 {
+        return Some(Outcome::Win(turn));
+    }
+    if playable(opponent) > 1 {
+        return Some(Outcome::Win(opponent));
+    }
+    if game.height() % 2 == 1 {
+        return None;
+    }
+
+    // Blocking a playable threat lets the other side block the cell on top of it as well
+    let blocked = |threat: &Threat| {
+        threats.iter().any(|below| {
+            below.playable && below.color != threat.color && (below.column, below.row + 1) == (threat.column, threat.row)
+        })
+    };
+    let has = |color: Color, parity: Parity| {
+        threats.iter().any(|threat| threat.color == color && threat.parity == parity && threat.lowest && !blocked(threat))
+    };
+    Some(if has(Color::Red, Parity::Odd) {
+        Outcome::Win(Color::Red)
+    } else if has(Color::Yellow, Parity::Even) {
+        Outcome::Win(Color::Yellow)
+    } else {
+        Outcome::Draw
+    })
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for color in [Color::Red, Color::Yellow] {
+            let threats: Vec<String> = self.threats_of(color)
+                .map(|threat| {
+                    let parity = match threat.parity {
+                        Parity::Odd => "odd",
+                        Parity::Even => "even",
+                    };
+                    let mut text = format!("{}/{} {parity}", threat.column + 1, threat.row + 1);
+                    if threat.playable { text.push_str(" playable") }
+                    if !threat.playable && !threat.lowest { text.push_str(" covered") }
+                    text
+                })
+                .collect();
+            match threats.is_empty() {
+                true => writeln!(f, "{color} threats: none")?,
+                false => writeln!(f, "{color} threats (column/row): {}", threats.join(", "))?,
+            }
+        }
+
+        write!(f, "Prediction: {}", describe(self.prediction))
+    }
+}
+
+pub fn describe(prediction: Option<Outcome>) -> String {
+    match prediction {
+        Some(Outcome::Win(winner)) => format!("{winner} wins"),
+        Some(Outcome::Draw) => "draw".to_string(),
+        None => "unclear, the parity rules need an even number of rows".to_string(),
+    }
+}
+
+// The predicted outcome first and the chain score to break ties
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Zugzwang;
+
+impl Evaluator for Zugzwang {
+    type Value = (i32, Score);
+
+    fn evaluate(&self, game: &Game) -> (i32, Score) {
+        let outcome = match analyse(game).prediction {
+            Some(Outcome::Win(Color::Red)) => 1,
+            Some(Outcome::Win(Color::Yellow)) => -1,
+            Some(Outcome::Draw) | None => 0,
+        };
+        (outcome, ChainScore.evaluate(game))
+    }
+
+    fn win(&self, game: &Game, winner: Color, plies: usize) -> (i32, Score) {
+        let outcome = match winner {
+            Color::Red => 2,
+            Color::Yellow => -2,
+        };
+        (outcome, ChainScore.win(game, winner, plies))
+    }
+
+    fn draw(&self, game: &Game) -> (i32, Score) {
+        (0, ChainScore.draw(game))
+    }
+
+    fn bounds(&self, game: &Game) -> ((i32, Score), (i32, Score)) {
+        let (low, high) = ChainScore.bounds(game);
+        ((i32::MIN, low), (i32::MAX, high))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: &str) -> Game {
+        Game::deserialize(rows, 4).unwrap()
+    }
+
+    fn moves(columns: &[usize]) -> Game {
+        let mut game = Game::new(7, 6, 4);
+        for &column in columns {
+            game.run(column).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn odd_threat_wins_for_red() {
+        let game = grid("
+            _ _ _ _ _ _ _
+            _ _ _ _ _ _ _
+            _ _ _ _ _ _ _
+            _ X X X _ _ _
+            _ O X O _ _ _
+            _ X O X _ O O
+        ");
+        let analysis = analyse(&game);
+        let lowest: Vec<(usize, usize, Parity)> = analysis.threats_of(Color::Red)
+            .filter(|threat| threat.lowest)
+            .map(|threat| (threat.column, threat.row, threat.parity))
+            .collect();
+        assert_eq!(lowest, [(0, 2, Parity::Odd), (4, 2, Parity::Odd)]);
+        assert_eq!(analysis.prediction, Some(Outcome::Win(Color::Red)));
+        assert_eq!(Zugzwang.evaluate(&game).0, 1);
+    }
+
+    #[test]
+    fn even_threat_wins_for_yellow() {
+        let game = grid("
+            _ _ _ _ _ _ _
+            _ _ _ _ _ _ _
+            _ _ _ _ _ _ _
+            _ _ _ _ _ _ _
+            _ O O O _ _ X
+            _ X O X _ _ X
+        ");
+        let analysis = analyse(&game);
+        assert!(analysis.threats_of(Color::Yellow).all(|threat| threat.parity == Parity::Even && !threat.playable));
+        assert_eq!(analysis.threats_of(Color::Yellow).count(), 2);
+        assert_eq!(analysis.prediction, Some(Outcome::Win(Color::Yellow)));
+        assert_eq!(Zugzwang.evaluate(&game).0, -1);
+    }
+
+    #[test]
+    fn playable_threats_decide_first() {
+        // Red to move completes the first column
+        assert_eq!(analyse(&moves(&[0, 1, 0, 1, 0, 1])).prediction, Some(Outcome::Win(Color::Red)));
+        // Yellow to move cannot block both ends of Red's row
+        assert_eq!(analyse(&moves(&[1, 1, 2, 2, 3])).prediction, Some(Outcome::Win(Color::Red)));
+        // Yellow blocks a single one
+        let single = analyse(&moves(&[0, 1, 0, 1, 0]));
+        assert!(single.threats_of(Color::Red).any(|threat| threat.playable));
+        assert_eq!(single.prediction, Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn odd_heights_have_no_prediction() {
+        assert_eq!(analyse(&Game::new(7, 6, 4)).prediction, Some(Outcome::Draw));
+        assert_eq!(analyse(&Game::new(7, 5, 4)).prediction, None);
+    }
+}